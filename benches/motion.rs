@@ -0,0 +1,123 @@
+// Replays a synthetic mouse drag against alwm running on Xvfb and reports how
+// long the window manager takes to work through it.
+//
+//     cargo bench --features xvfb --bench motion
+//
+// Opt in through the xvfb feature, which also pulls in XTest. Needs Xvfb on
+// the PATH and fails if it cannot run. ALWM_BENCH_DISPLAY picks the display
+// (default :99) and ALWM_BENCH_EVENTS the number of motion events (default
+// 5000).
+
+extern crate x11;
+
+use std::env;
+use std::ffi::CString;
+use std::process::{Child, Command};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use x11::{keysym, xlib, xtest};
+
+struct Reaper( Vec<Child> );
+
+impl Drop for Reaper {
+    fn drop( &mut self ) {
+        for child in self.0.iter_mut().rev() {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+    }
+}
+
+fn open_display( name: &str ) -> Option<*mut xlib::Display> {
+    let name = CString::new( name ).unwrap();
+    for _ in 0 .. 50 {
+        let display = unsafe { xlib::XOpenDisplay( name.as_ptr() ) };
+        if !display.is_null() {
+            return Some( display );
+        }
+        sleep( Duration::from_millis( 100 ) );
+    }
+    None
+}
+
+// Wait for a ConfigureNotify putting the window at x, or give up after 10s
+unsafe fn wait_for_x( display: *mut xlib::Display, window: xlib::Window, x: i32 ) -> bool {
+    let deadline = Instant::now() + Duration::from_secs( 10 );
+    let mut ev = xlib::XEvent { pad: [0; 24] };
+
+    while Instant::now() < deadline {
+        while xlib::XPending( display ) > 0 {
+            xlib::XNextEvent( display, &mut ev );
+            if ev.get_type() == xlib::ConfigureNotify {
+                let event = xlib::XConfigureEvent::from( ev );
+                if event.window == window && event.x == x {
+                    return true;
+                }
+            }
+        }
+        sleep( Duration::from_millis( 1 ) );
+    }
+    false
+}
+
+fn main() {
+    let display_name = env::var( "ALWM_BENCH_DISPLAY" ).unwrap_or( ":99".to_string() );
+    let events: i32 = env::var( "ALWM_BENCH_EVENTS" ).ok()
+        .and_then( |n| n.parse().ok() )
+        .unwrap_or( 5000 );
+
+    let mut children = Reaper( Vec::new() );
+
+    children.0.push( Command::new( "Xvfb" ).args( [ &display_name[..], "-screen", "0", "1920x1080x24" ] )
+        .spawn()
+        .unwrap_or_else( |e| panic!( "motion bench needs Xvfb on the PATH: {}", e ) ) );
+
+    let display = open_display( &display_name )
+        .unwrap_or_else( || panic!( "Xvfb did not come up on {}", display_name ) );
+
+    children.0.push( Command::new( env!( "CARGO_BIN_EXE_alwm" ) )
+        .env( "DISPLAY", &display_name )
+        .spawn()
+        .expect( "cannot start alwm" ) );
+    sleep( Duration::from_millis( 500 ) );
+
+    unsafe {
+        let root = xlib::XDefaultRootWindow( display );
+        let window = xlib::XCreateSimpleWindow( display, root, 100, 100, 400, 300, 0, 0, 0 );
+        xlib::XSelectInput( display, window, xlib::StructureNotifyMask );
+        xlib::XMapWindow( display, window );
+        xlib::XSync( display, 0 );
+        sleep( Duration::from_millis( 200 ) );
+
+        let super_key = xlib::XKeysymToKeycode( display, keysym::XK_Super_L as u64 ) as u32;
+
+        // Grab the window with Super+Button1 and sweep it back and forth
+        xtest::XTestFakeMotionEvent( display, -1, 300, 250, 0 );
+        xtest::XTestFakeKeyEvent( display, super_key, 1, 0 );
+        xtest::XTestFakeButtonEvent( display, 1, 1, 0 );
+        xlib::XSync( display, 0 );
+
+        let start = Instant::now();
+        for i in 0 .. events {
+            xtest::XTestFakeMotionEvent( display, -1, 300 + ( i % 200 ), 250, 0 );
+        }
+        // Finish on a position we can recognise
+        xtest::XTestFakeMotionEvent( display, -1, 700, 250, 0 );
+        xlib::XFlush( display );
+
+        let done = wait_for_x( display, window, 500 );
+        let elapsed = start.elapsed();
+
+        xtest::XTestFakeButtonEvent( display, 1, 0, 0 );
+        xtest::XTestFakeKeyEvent( display, super_key, 0, 0 );
+        xlib::XSync( display, 0 );
+        xlib::XCloseDisplay( display );
+
+        assert!( done, "motion bench: window never reached its final position" );
+
+        let ms = elapsed.as_secs_f64() * 1000.0;
+        println!( "motion bench: {} motion events in {:.1} ms ({:.2} us/event)",
+            events + 1, ms, ms * 1000.0 / ( events + 1 ) as f64 );
+    }
+}
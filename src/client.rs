@@ -0,0 +1,187 @@
+use std::cmp::{max, min};
+use x11::xlib;
+use config;
+use config::Direction;
+use frame::Frame;
+
+// A top level window we manage and the state we keep for it
+pub struct Client {
+    pub window: xlib::Window,
+    pub x: i32,
+    pub y: i32,
+    pub w: i32,
+    pub h: i32,
+    pub depth: i32,
+    pub bw: i32,
+    // Set in reparenting mode. x and y are then the frame's position.
+    pub frame: Option<Frame>,
+    // Unmaps we caused ourselves and should not take as a withdraw
+    pub ignore_unmaps: u32,
+    pub hidden: bool,
+    // Geometry to go back to when unmaximized
+    pub saved_geom: Option<(i32, i32, i32, i32)>,
+    // From _NET_WM_PID, 0 when unknown
+    pub pid: u32,
+    // The terminal this window took the place of
+    pub swallowed: Option<xlib::Window>,
+    // ICCCM focus model: the input field of WM_HINTS and whether
+    // WM_TAKE_FOCUS is in WM_PROTOCOLS
+    pub input: bool,
+    pub take_focus: bool,
+    // Screen edge space the window reserves, laid out like
+    // _NET_WM_STRUT_PARTIAL: left, right, top and bottom widths, then the
+    // start and end of each along its edge
+    pub strut: Option<[i32; 12]>,
+    // Size and focus state the border pixmap was last drawn for
+    pub drawn: Option<(i32, i32, bool)>,
+    pub title: String,
+    pub class: String,
+    pub instance: String,
+    pub min_w: i32,
+    pub min_h: i32,
+    pub max_w: i32,
+    pub max_h: i32,
+    pub base_w: i32,
+    pub base_h: i32,
+    pub inc_w: i32,
+    pub inc_h: i32,
+}
+
+impl Client {
+    pub fn new( window: xlib::Window, x: i32, y: i32, w: i32, h: i32 ) -> Client {
+        Client {
+            window,
+            x,
+            y,
+            w,
+            h,
+            depth: 0,
+            bw: 0,
+            frame: None,
+            ignore_unmaps: 0,
+            hidden: false,
+            saved_geom: None,
+            pid: 0,
+            swallowed: None,
+            input: true,
+            take_focus: false,
+            strut: None,
+            drawn: None,
+            title: String::new(),
+            class: String::new(),
+            instance: String::new(),
+            min_w: 1,
+            min_h: 1,
+            max_w: 0,
+            max_h: 0,
+            base_w: 0,
+            base_h: 0,
+            inc_w: 0,
+            inc_h: 0,
+        }
+    }
+
+    // Space around the client area as left, right, top and bottom, measured
+    // from the outer corner at x and y
+    pub fn extents( &self ) -> (i32, i32, i32, i32) {
+        let title = if self.frame.is_some() { config::TITLE_HEIGHT } else { 0 };
+        ( self.bw, self.bw, self.bw + title, self.bw )
+    }
+
+    // No input windows never want the keyboard, everyone else either takes
+    // it from us or asks for it through WM_TAKE_FOCUS
+    pub fn can_focus( &self ) -> bool {
+        self.input || self.take_focus
+    }
+
+    // Whether the class or instance is one of names, ignoring case
+    pub fn class_in( &self, names: &[&str] ) -> bool {
+        names.iter().any( |name| self.class.eq_ignore_ascii_case( name ) ||
+                                 self.instance.eq_ignore_ascii_case( name ) )
+    }
+
+    // Take the constraints we honour from WM_NORMAL_HINTS
+    pub fn set_size_hints( &mut self, hints: Option<&xlib::XSizeHints> ) {
+        self.min_w = 1;
+        self.min_h = 1;
+        self.max_w = 0;
+        self.max_h = 0;
+        self.base_w = 0;
+        self.base_h = 0;
+        self.inc_w = 0;
+        self.inc_h = 0;
+
+        let hints = match hints {
+            Some(hints) => hints,
+            None => return,
+        };
+
+        if hints.flags & xlib::PBaseSize != 0 {
+            self.base_w = hints.base_width;
+            self.base_h = hints.base_height;
+        } else if hints.flags & xlib::PMinSize != 0 {
+            self.base_w = hints.min_width;
+            self.base_h = hints.min_height;
+        }
+        if hints.flags & xlib::PMinSize != 0 {
+            self.min_w = max( 1, hints.min_width );
+            self.min_h = max( 1, hints.min_height );
+        }
+        if hints.flags & xlib::PMaxSize != 0 {
+            self.max_w = hints.max_width;
+            self.max_h = hints.max_height;
+        }
+        if hints.flags & xlib::PResizeInc != 0 {
+            self.inc_w = hints.width_inc;
+            self.inc_h = hints.height_inc;
+        }
+    }
+
+    // Round a requested size to what the client accepts
+    pub fn apply_size_hints( &self, w: i32, h: i32 ) -> (i32, i32) {
+        let mut w = w;
+        let mut h = h;
+
+        if self.inc_w > 1 {
+            w -= ( w - self.base_w ).rem_euclid( self.inc_w );
+        }
+        if self.inc_h > 1 {
+            h -= ( h - self.base_h ).rem_euclid( self.inc_h );
+        }
+
+        w = max( w, self.min_w );
+        h = max( h, self.min_h );
+        if self.max_w > 0 {
+            w = min( w, self.max_w );
+        }
+        if self.max_h > 0 {
+            h = min( h, self.max_h );
+        }
+        ( max( 1, w ), max( 1, h ) )
+    }
+
+    pub fn center( &self ) -> (i32, i32) {
+        ( self.x + self.w / 2, self.y + self.h / 2 )
+    }
+
+    // How far `other` is from us when looking in `dir`, or None if it is not
+    // in that direction at all. Distance along the direction counts once and
+    // drift across it counts double, so windows roughly in line win over
+    // closer ones off to the side.
+    pub fn distance_to( &self, other: &Client, dir: Direction ) -> Option<i32> {
+        let (cx, cy) = self.center();
+        let (ox, oy) = other.center();
+
+        let (along, across) = match dir {
+            Direction::Left => ( cx - ox, oy - cy ),
+            Direction::Right => ( ox - cx, oy - cy ),
+            Direction::Up => ( cy - oy, ox - cx ),
+            Direction::Down => ( oy - cy, ox - cx ),
+        };
+
+        if along <= 0 {
+            return None;
+        }
+        Some( along + 2 * across.abs() )
+    }
+}
@@ -0,0 +1,179 @@
+use libc::c_ulong;
+use std::ffi::CString;
+use std::fmt;
+
+use x11::xlib;
+
+// A colour from the config. Hex and rgb: values are checked when parsed,
+// names are checked against the X colour database when allocated.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Color {
+    Rgb( u16, u16, u16 ),
+    Named( String ),
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ColorError {
+    Empty,
+    BadHex( String ),
+    BadRgb( String ),
+    UnknownName( String ),
+    AllocFailed( String ),
+}
+
+impl fmt::Display for ColorError {
+    fn fmt( &self, f: &mut fmt::Formatter ) -> fmt::Result {
+        match *self {
+            ColorError::Empty => write!( f, "empty colour" ),
+            ColorError::BadHex( ref s ) => write!( f, "{:?} is not #rgb, #rrggbb, #rrrgggbbb or #rrrrggggbbbb", s ),
+            ColorError::BadRgb( ref s ) => write!( f, "{:?} is not rgb:r/g/b with 1 to 4 hex digits each", s ),
+            ColorError::UnknownName( ref s ) => write!( f, "{:?} is not a known X colour name", s ),
+            ColorError::AllocFailed( ref s ) => write!( f, "could not allocate {:?} in the colormap", s ),
+        }
+    }
+}
+
+impl Color {
+    // Accepts #rgb and its longer forms, rgb:r/g/b and X11 colour names
+    pub fn parse( s: &str ) -> Result<Color, ColorError> {
+        let s = s.trim();
+        if s.is_empty() {
+            return Err( ColorError::Empty );
+        }
+
+        if let Some(hex) = s.strip_prefix( '#' ) {
+            let digits = hex.len() / 3;
+            if hex.len() % 3 != 0 || digits == 0 || digits > 4 {
+                return Err( ColorError::BadHex( s.to_string() ) );
+            }
+            // get() rather than slicing, non-ASCII input would not split on
+            // char boundaries
+            let channel = |i: usize| hex.get( i * digits .. ( i + 1 ) * digits ).and_then( scale_hex );
+            return match ( channel( 0 ), channel( 1 ), channel( 2 ) ) {
+                ( Some(r), Some(g), Some(b) ) => Ok( Color::Rgb( r, g, b ) ),
+                _ => Err( ColorError::BadHex( s.to_string() ) ),
+            };
+        }
+
+        if s.get( ..4 ).is_some_and( |prefix| prefix.eq_ignore_ascii_case( "rgb:" ) ) {
+            let parts: Vec<Option<u16>> = s[4..].split( '/' ).map( scale_hex ).collect();
+            return match parts[..] {
+                [ Some(r), Some(g), Some(b) ] => Ok( Color::Rgb( r, g, b ) ),
+                _ => Err( ColorError::BadRgb( s.to_string() ) ),
+            };
+        }
+
+        if s.chars().all( |c| c.is_ascii_alphanumeric() || c == ' ' ) {
+            Ok( Color::Named( s.to_string() ) )
+        } else {
+            Err( ColorError::UnknownName( s.to_string() ) )
+        }
+    }
+
+    /// # Safety
+    /// `display` must be an open connection.
+    pub unsafe fn alloc( &self, display: *mut xlib::Display ) -> Result<c_ulong, ColorError> {
+        let cmap = xlib::XDefaultColormap( display, xlib::XDefaultScreen( display ) );
+        let mut color: xlib::XColor = ::std::mem::zeroed();
+
+        match *self {
+            Color::Rgb( r, g, b ) => {
+                color.red = r;
+                color.green = g;
+                color.blue = b;
+                color.flags = xlib::DoRed|xlib::DoGreen|xlib::DoBlue;
+            },
+            Color::Named( ref name ) => {
+                let cname = CString::new( name.as_str() ).map_err( |_| ColorError::UnknownName( name.clone() ) )?;
+                if xlib::XParseColor( display, cmap, cname.as_ptr(), &mut color ) == 0 {
+                    return Err( ColorError::UnknownName( name.clone() ) );
+                }
+            },
+        }
+
+        if xlib::XAllocColor( display, cmap, &mut color ) == 0 {
+            return Err( ColorError::AllocFailed( self.to_string() ) );
+        }
+        Ok( color.pixel )
+    }
+}
+
+impl fmt::Display for Color {
+    fn fmt( &self, f: &mut fmt::Formatter ) -> fmt::Result {
+        match *self {
+            Color::Rgb( r, g, b ) => write!( f, "rgb:{:04x}/{:04x}/{:04x}", r, g, b ),
+            Color::Named( ref name ) => write!( f, "{}", name ),
+        }
+    }
+}
+
+// One to four hex digits scaled to the full 16 bit range, the way X does
+fn scale_hex( s: &str ) -> Option<u16> {
+    if s.is_empty() || s.len() > 4 || !s.chars().all( |c| c.is_ascii_hexdigit() ) {
+        return None;
+    }
+    let value = u32::from_str_radix( s, 16 ).ok()?;
+    let max = ( 1u32 << ( 4 * s.len() ) ) - 1;
+    Some( ( value * 0xffff / max ) as u16 )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn short_hex_repeats_each_digit() {
+        assert_eq!( Color::parse( "#f08" ), Ok( Color::Rgb( 0xffff, 0, 0x8888 ) ) );
+    }
+
+    #[test]
+    fn two_digit_hex() {
+        assert_eq!( Color::parse( "#ff8000" ), Ok( Color::Rgb( 0xffff, 0x8080, 0 ) ) );
+    }
+
+    #[test]
+    fn four_digit_hex_is_taken_as_is() {
+        assert_eq!( Color::parse( "#123456789abc" ), Ok( Color::Rgb( 0x1234, 0x5678, 0x9abc ) ) );
+    }
+
+    #[test]
+    fn rgb_channels_can_differ_in_length() {
+        assert_eq!( Color::parse( "rgb:f/ff/fff" ), Ok( Color::Rgb( 0xffff, 0xffff, 0xffff ) ) );
+        assert_eq!( Color::parse( "RGB:8/80/800" ), Ok( Color::Rgb( 0x8888, 0x8080, 0x8007 ) ) );
+    }
+
+    #[test]
+    fn names_may_contain_spaces() {
+        assert_eq!( Color::parse( " slate gray " ), Ok( Color::Named( "slate gray".to_string() ) ) );
+    }
+
+    #[test]
+    fn empty_input() {
+        assert_eq!( Color::parse( "" ), Err( ColorError::Empty ) );
+        assert_eq!( Color::parse( "   " ), Err( ColorError::Empty ) );
+    }
+
+    #[test]
+    fn wrong_lengths() {
+        assert_eq!( Color::parse( "#" ), Err( ColorError::BadHex( "#".to_string() ) ) );
+        assert_eq!( Color::parse( "#ffff" ), Err( ColorError::BadHex( "#ffff".to_string() ) ) );
+        assert_eq!( Color::parse( "#fffffffffffffff" ), Err( ColorError::BadHex( "#fffffffffffffff".to_string() ) ) );
+        assert_eq!( Color::parse( "rgb:" ), Err( ColorError::BadRgb( "rgb:".to_string() ) ) );
+        assert_eq!( Color::parse( "rgb:ff/ff" ), Err( ColorError::BadRgb( "rgb:ff/ff".to_string() ) ) );
+        assert_eq!( Color::parse( "rgb:fffff/0/0" ), Err( ColorError::BadRgb( "rgb:fffff/0/0".to_string() ) ) );
+    }
+
+    #[test]
+    fn bad_digits() {
+        assert_eq!( Color::parse( "#ggg" ), Err( ColorError::BadHex( "#ggg".to_string() ) ) );
+        assert_eq!( Color::parse( "rgb:0/0/x" ), Err( ColorError::BadRgb( "rgb:0/0/x".to_string() ) ) );
+    }
+
+    #[test]
+    fn non_ascii_is_an_error_not_a_panic() {
+        assert_eq!( Color::parse( "#aé" ), Err( ColorError::BadHex( "#aé".to_string() ) ) );
+        assert_eq!( Color::parse( "#ééé" ), Err( ColorError::BadHex( "#ééé".to_string() ) ) );
+        assert_eq!( Color::parse( "aéé" ), Err( ColorError::UnknownName( "aéé".to_string() ) ) );
+        assert_eq!( Color::parse( "rgb:é/0/0" ), Err( ColorError::BadRgb( "rgb:é/0/0".to_string() ) ) );
+    }
+}
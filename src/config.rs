@@ -6,6 +6,7 @@ use std::ffi::CString;
 pub const MODKEY1: u32 = xlib::Mod1Mask;
 pub const MODKEY2: u32 = xlib::Mod4Mask;
 pub const SHIFT: u32 = xlib::ShiftMask;
+pub const CONTROL: u32 = xlib::ControlMask;
 
 // Key combos. We add our bindings here for wm actions
 pub const EXIT_KEY: KeyCmd<'static> = KeyCmd{ key: "F1", modifier: MODKEY2|SHIFT };
@@ -13,18 +14,159 @@ pub const TERM_KEY: KeyCmd<'static> = KeyCmd{ key: "t", modifier: MODKEY2|SHIFT
 pub const RUN_KEY: KeyCmd<'static> = KeyCmd{ key: "r", modifier: MODKEY2 };
 
 pub const RUN: &'static str = "dmenu_run";
+// Menu program fed one line per choice on stdin, printing the picked line.
+// Used to pick a minimized window to restore.
+pub const MENU: &[&str] = &[ "dmenu", "-i", "-l", "10", "-p", "restore" ];
 pub const TERMINAL: &'static str = "termite";
 
+// Root key bindings. These are grabbed on the root window at all times.
+pub const KEYS: &[KeyBind] = &[
+    KeyBind{ key: EXIT_KEY, action: Action::Exit },
+    KeyBind{ key: TERM_KEY, action: Action::Spawn( TERMINAL ) },
+    KeyBind{ key: RUN_KEY, action: Action::Spawn( RUN ) },
+    KeyBind{ key: KeyCmd{ key: "h", modifier: MODKEY2 }, action: Action::Focus( Direction::Left ) },
+    KeyBind{ key: KeyCmd{ key: "j", modifier: MODKEY2 }, action: Action::Focus( Direction::Down ) },
+    KeyBind{ key: KeyCmd{ key: "k", modifier: MODKEY2 }, action: Action::Focus( Direction::Up ) },
+    KeyBind{ key: KeyCmd{ key: "l", modifier: MODKEY2 }, action: Action::Focus( Direction::Right ) },
+    KeyBind{ key: KeyCmd{ key: "h", modifier: MODKEY2|SHIFT }, action: Action::Swap( Direction::Left ) },
+    KeyBind{ key: KeyCmd{ key: "j", modifier: MODKEY2|SHIFT }, action: Action::Swap( Direction::Down ) },
+    KeyBind{ key: KeyCmd{ key: "k", modifier: MODKEY2|SHIFT }, action: Action::Swap( Direction::Up ) },
+    KeyBind{ key: KeyCmd{ key: "l", modifier: MODKEY2|SHIFT }, action: Action::Swap( Direction::Right ) },
+    KeyBind{ key: KeyCmd{ key: "Left", modifier: MODKEY2 }, action: Action::Move( Direction::Left ) },
+    KeyBind{ key: KeyCmd{ key: "Right", modifier: MODKEY2 }, action: Action::Move( Direction::Right ) },
+    KeyBind{ key: KeyCmd{ key: "Up", modifier: MODKEY2 }, action: Action::Move( Direction::Up ) },
+    KeyBind{ key: KeyCmd{ key: "Down", modifier: MODKEY2 }, action: Action::Move( Direction::Down ) },
+    KeyBind{ key: KeyCmd{ key: "Left", modifier: MODKEY2|SHIFT }, action: Action::Resize( Direction::Left ) },
+    KeyBind{ key: KeyCmd{ key: "Right", modifier: MODKEY2|SHIFT }, action: Action::Resize( Direction::Right ) },
+    KeyBind{ key: KeyCmd{ key: "Up", modifier: MODKEY2|SHIFT }, action: Action::Resize( Direction::Up ) },
+    KeyBind{ key: KeyCmd{ key: "Down", modifier: MODKEY2|SHIFT }, action: Action::Resize( Direction::Down ) },
+    KeyBind{ key: KeyCmd{ key: "Left", modifier: MODKEY2|CONTROL }, action: Action::Snap( Direction::Left ) },
+    KeyBind{ key: KeyCmd{ key: "Right", modifier: MODKEY2|CONTROL }, action: Action::Snap( Direction::Right ) },
+    KeyBind{ key: KeyCmd{ key: "Up", modifier: MODKEY2|CONTROL }, action: Action::Snap( Direction::Up ) },
+    KeyBind{ key: KeyCmd{ key: "Down", modifier: MODKEY2|CONTROL }, action: Action::Snap( Direction::Down ) },
+    KeyBind{ key: KeyCmd{ key: "c", modifier: MODKEY2 }, action: Action::Center },
+    KeyBind{ key: KeyCmd{ key: "Tab", modifier: MODKEY1 }, action: Action::FocusNext },
+    KeyBind{ key: KeyCmd{ key: "Tab", modifier: MODKEY1|SHIFT }, action: Action::FocusPrev },
+    KeyBind{ key: KeyCmd{ key: "Tab", modifier: MODKEY2 }, action: Action::Switcher },
+    KeyBind{ key: KeyCmd{ key: "w", modifier: MODKEY2 }, action: Action::EnterKeymap( "window" ) },
+    KeyBind{ key: KeyCmd{ key: "e", modifier: MODKEY2 }, action: Action::EnterKeymap( "resize" ) },
+    KeyBind{ key: KeyCmd{ key: "u", modifier: MODKEY2 }, action: Action::FocusUrgent },
+    KeyBind{ key: KeyCmd{ key: "m", modifier: MODKEY2 }, action: Action::Minimize },
+    KeyBind{ key: KeyCmd{ key: "m", modifier: MODKEY2|SHIFT }, action: Action::RestoreLast },
+    KeyBind{ key: KeyCmd{ key: "m", modifier: MODKEY2|CONTROL }, action: Action::PickHidden },
+    KeyBind{ key: KeyCmd{ key: "grave", modifier: MODKEY2 }, action: Action::Scratchpad( "term" ) },
+    KeyBind{ key: KeyCmd{ key: "n", modifier: MODKEY2 }, action: Action::Scratchpad( "notes" ) },
+    KeyBind{ key: KeyCmd{ key: "b", modifier: MODKEY2 }, action: Action::RunOrRaise( "firefox", &[ "firefox" ] ) },
+    KeyBind{ key: KeyCmd{ key: "v", modifier: MODKEY2 }, action: Action::RunOrRaise( "emacs", &[ "emacs" ] ) },
+];
+
+// Named keymaps entered from a binding. While one is active the keyboard is
+// grabbed and keys are looked up in its bindings instead of KEYS. A one shot
+// keymap is a chord prefix and is left after the next key press or once its
+// timeout (in milliseconds, 0 for none) runs out. Escape always leaves.
+pub const KEYMAPS: &[Keymap] = &[
+    Keymap{ name: "window", oneshot: true, timeout: 1500, binds: &[
+        KeyBind{ key: KeyCmd{ key: "t", modifier: 0 }, action: Action::Spawn( TERMINAL ) },
+        KeyBind{ key: KeyCmd{ key: "r", modifier: 0 }, action: Action::Spawn( RUN ) },
+        KeyBind{ key: KeyCmd{ key: "e", modifier: 0 }, action: Action::EnterKeymap( "resize" ) },
+        KeyBind{ key: KeyCmd{ key: "h", modifier: 0 }, action: Action::Focus( Direction::Left ) },
+        KeyBind{ key: KeyCmd{ key: "j", modifier: 0 }, action: Action::Focus( Direction::Down ) },
+        KeyBind{ key: KeyCmd{ key: "k", modifier: 0 }, action: Action::Focus( Direction::Up ) },
+        KeyBind{ key: KeyCmd{ key: "l", modifier: 0 }, action: Action::Focus( Direction::Right ) },
+    ] },
+    Keymap{ name: "resize", oneshot: false, timeout: 0, binds: &[
+        KeyBind{ key: KeyCmd{ key: "Left", modifier: 0 }, action: Action::Resize( Direction::Left ) },
+        KeyBind{ key: KeyCmd{ key: "Right", modifier: 0 }, action: Action::Resize( Direction::Right ) },
+        KeyBind{ key: KeyCmd{ key: "Up", modifier: 0 }, action: Action::Resize( Direction::Up ) },
+        KeyBind{ key: KeyCmd{ key: "Down", modifier: 0 }, action: Action::Resize( Direction::Down ) },
+        KeyBind{ key: KeyCmd{ key: "Left", modifier: SHIFT }, action: Action::Move( Direction::Left ) },
+        KeyBind{ key: KeyCmd{ key: "Right", modifier: SHIFT }, action: Action::Move( Direction::Right ) },
+        KeyBind{ key: KeyCmd{ key: "Up", modifier: SHIFT }, action: Action::Move( Direction::Up ) },
+        KeyBind{ key: KeyCmd{ key: "Down", modifier: SHIFT }, action: Action::Move( Direction::Down ) },
+        KeyBind{ key: KeyCmd{ key: "Return", modifier: 0 }, action: Action::LeaveKeymap },
+    ] },
+];
+
+// Scratchpads are windows summoned and dismissed with one key. The window is
+// found by WM_CLASS class and instance (empty matches anything) and started
+// with command when missing. It is shown on the monitor under the pointer at
+// geometry, given as x, y, width and height in percent of the monitor.
+pub const SCRATCHPADS: &[Scratchpad] = &[
+    Scratchpad{ name: "term", class: "", instance: "scratchterm",
+                command: &[ TERMINAL, "--name", "scratchterm" ], geometry: ( 10, 0, 80, 40 ) },
+    Scratchpad{ name: "notes", class: "", instance: "scratchnotes",
+                command: &[ TERMINAL, "--name", "scratchnotes", "-e", "vim notes.md" ], geometry: ( 60, 10, 35, 60 ) },
+];
+
+// Terminal swallowing. A window started from a terminal listed in
+// SWALLOW_TERMINALS takes the terminal's place and the terminal is hidden
+// until the window goes away. Windows whose class or instance is listed in
+// NO_SWALLOW never swallow. Names are matched ignoring case.
+pub const SWALLOW: bool = true;
+pub const SWALLOW_TERMINALS: &[&str] = &[ "termite", "xterm", "urxvt", "alacritty", "st", "kitty" ];
+pub const NO_SWALLOW: &[&str] = &[ "xev", "firefox", "gimp" ];
+
+// Name published in the _ALWM_MODE root property while no keymap is active
+pub const DEFAULT_MODE: &str = "default";
+
 // Mouse commands. We add our binds here for wm actions
 pub const MOUSE_MOVE: MouseCmd = MouseCmd{ button: 1, modifier: MODKEY2 };
 pub const MOUSE_RAISE: MouseCmd = MouseCmd{ button: 1, modifier: 0 };
 pub const MOUSE_RESIZE: MouseCmd = MouseCmd{ button: 3, modifier: MODKEY2 };
 
-// Mouse focus behavior
-pub const SLOPPYFOCUS: bool = false;
+// Keyboard move and resize step in pixels. Windows with size increments
+// (terminals) resize by whole increments instead.
+pub const MOVE_STEP: i32 = 20;
+pub const RESIZE_STEP: i32 = 20;
+
+// Pixels of a window that stay on screen when it is moved or resized
+pub const KEEP_VISIBLE: i32 = 32;
+
+// While moving with the mouse, window edges snap to monitor edges and other
+// windows within this many pixels. 0 turns snapping off.
+pub const SNAP_DISTANCE: i32 = 12;
+
+// Dragging the pointer onto a monitor edge tiles the window to that half,
+// to a quarter when within AERO_CORNER pixels of a corner, or maximizes it
+// at the top edge. An outline previews the result until the button is let go.
+pub const AERO_SNAP: bool = true;
+pub const AERO_CORNER: i32 = 48;
+pub const SNAP_OUTLINE: Border<'static> = Border{ size: 3, color: "rgb:00/bb/aa" };
+
+// Outline mode for mouse move and resize. The drag is shown as a rectangle
+// and the window only changes once the button is released, which is much
+// lighter on slow machines and remote X.
+pub const WIREFRAME: bool = false;
+pub const WIREFRAME_WIDTH: i32 = 2;
+
+// Mouse focus behavior. Click focuses windows when they are clicked, Sloppy
+// when the pointer enters them and Strict also drops focus to the root when
+// the pointer leaves every window. With FOCUS_DELAY the pointer has to rest
+// that many milliseconds in a window before Sloppy or Strict focus it.
+pub const FOCUS_MODEL: FocusModel = FocusModel::Click;
+pub const FOCUS_DELAY: u64 = 0;
+
+// Raise windows when they get focus, RAISE_DELAY milliseconds later.
+// Clicking a window always raises it.
+pub const RAISE_ON_FOCUS: bool = true;
+pub const RAISE_DELAY: u64 = 0;
+
+// Move the pointer to the middle of a window focused from the keyboard or by
+// a pager, unless it is already over it
+pub const WARP_POINTER: bool = false;
+
+// Focus stealing prevention for new windows, _NET_ACTIVE_WINDOW requests
+// from applications and clients setting focus themselves. Smart only lets a
+// window take focus if its _NET_WM_USER_TIME is not older than the last key
+// or button press; Allow and Deny let every or no such window through.
+// Windows turned away are marked urgent instead.
+pub const FOCUS_STEALING: FocusStealing = FocusStealing::Smart;
+
+// Borders. Colours can be "#rrggbb", "#rgb", "rgb:ff/ff/ff" or an X11 colour
+// name like "slate gray". Anything that does not parse or allocate is
+// reported on stderr and drawn with FALLBACK_COLOR instead.
+pub const FALLBACK_COLOR: &str = "#808080";
 
-// Borders. NOTE Color format is "rgb:ff/ff/ff" failure to use this code format
-// will result in a segfault.
 pub const BORDER0: Border<'static> = Border{ size: 2, color: "rgb:a5/a5/a5" };
 pub const BORDER1: Border<'static> = Border{ size: 2, color: "rgb:18/18/18" };
 pub const BORDER2: Border<'static> = Border{ size: 2, color: "rgb:aa/ff/33" };
@@ -35,6 +177,33 @@ pub const FBORDER1: Border<'static> = Border{ size: 2, color: "rgb:00/ff/00" };
 pub const FBORDER2: Border<'static> = Border{ size: 2, color: "rgb:00/00/ff" };
 pub const FBORDER3: Border<'static> = Border{ size: 2, color: "rgb:ff/ff/ff" };
 
+// Unfocused windows asking for attention through the WM_HINTS urgency flag or
+// _NET_WM_STATE_DEMANDS_ATTENTION
+pub const UBORDER0: Border<'static> = Border{ size: 2, color: "rgb:ff/aa/00" };
+pub const UBORDER1: Border<'static> = Border{ size: 2, color: "rgb:18/18/18" };
+pub const UBORDER2: Border<'static> = Border{ size: 2, color: "rgb:ff/aa/00" };
+pub const UBORDER3: Border<'static> = Border{ size: 2, color: "rgb:ff/33/00" };
+
+// Fontconfig names for all text alwm draws. Characters missing from the
+// first font are taken from the next one that has them.
+pub const FONTS: &[&str] = &[ "monospace:size=10", "Noto Sans CJK JP:size=10", "Noto Color Emoji:size=10" ];
+
+// Reparenting mode wraps every client in a frame with a title bar holding
+// minimize, maximize and close buttons. Dragging the title bar moves the
+// window. Title colors are [ background, foreground ] for unfocused and
+// focused windows.
+pub const REPARENT: bool = false;
+pub const TITLE_HEIGHT: i32 = 18;
+pub const TITLE_NORM: [&str; 2] = [ BORDER1.color, BORDER0.color ];
+pub const TITLE_SEL: [&str; 2] = [ FBORDER0.color, FBORDER3.color ];
+
+// Window switcher. Colors are [ background, foreground ] for normal and
+// selected lines.
+pub const SWITCHER_WIDTH: u32 = 600;
+pub const SWITCHER_ROWS: usize = 12;
+pub const SWITCHER_NORM: [&str; 2] = [ BORDER1.color, BORDER0.color ];
+pub const SWITCHER_SEL: [&str; 2] = [ BORDER3.color, BORDER1.color ];
+
 pub const NUM_UNFOCUSED_BORDERS: usize = 4;
 pub const UNFOCUSED_BORDERS: [Border<'static>; NUM_UNFOCUSED_BORDERS] =
             [ BORDER0, BORDER1, BORDER2, BORDER3 ];
@@ -43,6 +212,10 @@ pub const NUM_FOCUSED_BORDERS: usize = 4;
 pub const FOCUS_BORDERS: [Border<'static>; NUM_FOCUSED_BORDERS] =
             [ FBORDER0, FBORDER1, FBORDER2, FBORDER3 ];
 
+pub const NUM_URGENT_BORDERS: usize = 4;
+pub const URGENT_BORDERS: [Border<'static>; NUM_URGENT_BORDERS] =
+            [ UBORDER0, UBORDER1, UBORDER2, UBORDER3 ];
+
 
 
 // Structs for configs
@@ -54,11 +227,13 @@ pub struct Border<'a> {
 pub struct BorderInfo {
     focus_size: i32,
     unfocus_size: i32,
+    urgent_size: i32,
 }
 
 impl BorderInfo {
     pub fn new( focus: [Border;NUM_FOCUSED_BORDERS],
-                unfocus: [Border;NUM_UNFOCUSED_BORDERS] ) -> BorderInfo {
+                unfocus: [Border;NUM_UNFOCUSED_BORDERS],
+                urgent: [Border;NUM_URGENT_BORDERS] ) -> BorderInfo {
         let focus_iter = focus.into_iter();
         let mut focus_size = 0;
         for border in focus_iter {
@@ -71,9 +246,12 @@ impl BorderInfo {
             unfocus_size += border.size;
         }
 
+        let urgent_size = urgent.iter().map( |border| border.size ).sum();
+
         BorderInfo {
             focus_size: focus_size,
             unfocus_size: unfocus_size,
+            urgent_size,
         }
     }
 
@@ -83,10 +261,100 @@ impl BorderInfo {
     pub fn get_unfocus_size( &self ) -> i32 {
         self.unfocus_size
     }
+    pub fn get_urgent_size( &self ) -> i32 {
+        self.urgent_size
+    }
 }
 
 
-#[derive(PartialEq)]
+#[derive(Clone, Copy, PartialEq)]
+pub enum FocusModel {
+    Click,
+    Sloppy,
+    Strict,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum FocusStealing {
+    Allow,
+    Smart,
+    Deny,
+}
+
+// Things a key binding can do
+#[derive(Clone, Copy, PartialEq)]
+pub enum Action {
+    Exit,
+    Spawn( &'static str ),
+    EnterKeymap( &'static str ),
+    LeaveKeymap,
+    Move( Direction ),
+    Resize( Direction ),
+    Snap( Direction ),
+    Center,
+    Focus( Direction ),
+    Swap( Direction ),
+    FocusNext,
+    FocusPrev,
+    Switcher,
+    FocusUrgent,
+    Minimize,
+    RestoreLast,
+    PickHidden,
+    Scratchpad( &'static str ),
+    // Focus a window whose WM_CLASS class or instance is the given name,
+    // ignoring case, or run the command when there is none. Pressing it
+    // again cycles through the matching windows.
+    RunOrRaise( &'static str, &'static [&'static str] ),
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Direction {
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
+#[derive(Clone, Copy)]
+pub struct KeyBind {
+    pub key: KeyCmd<'static>,
+    pub action: Action,
+}
+
+pub struct Keymap {
+    pub name: &'static str,
+    pub oneshot: bool,
+    pub timeout: u64,
+    pub binds: &'static [KeyBind],
+}
+
+impl Keymap {
+    pub fn find( name: &str ) -> Option<usize> {
+        KEYMAPS.iter().position( |map| map.name == name )
+    }
+}
+
+pub struct Scratchpad {
+    pub name: &'static str,
+    pub class: &'static str,
+    pub instance: &'static str,
+    pub command: &'static [&'static str],
+    pub geometry: (i32, i32, i32, i32),
+}
+
+impl Scratchpad {
+    pub fn find( name: &str ) -> Option<&'static Scratchpad> {
+        SCRATCHPADS.iter().find( |pad| pad.name == name )
+    }
+
+    pub fn matches( &self, class: &str, instance: &str ) -> bool {
+        ( self.class.is_empty() || self.class == class ) &&
+        ( self.instance.is_empty() || self.instance == instance )
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
 pub struct MouseCmd {
     pub button: u32,
     pub modifier: u32,
@@ -101,7 +369,7 @@ impl MouseCmd {
     }
 }
 
-#[derive(PartialEq)]
+#[derive(Clone, Copy, PartialEq)]
 pub struct KeyCmd<'a> {
     key: &'a str,
     modifier: u32,
@@ -0,0 +1,156 @@
+use libc::c_ulong;
+use std::ptr::null_mut;
+use std::rc::Rc;
+
+use x11::{xft, xlib};
+use config;
+use text::{Canvas, Text};
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum FrameButton {
+    Minimize,
+    Maximize,
+    Close,
+}
+
+// Buttons from the right edge of the title bar inwards
+const BUTTONS: [FrameButton; 3] = [ FrameButton::Close, FrameButton::Maximize, FrameButton::Minimize ];
+
+// Text and GC shared by every title bar. colors are the normal background
+// and foreground followed by the focused ones.
+pub struct FrameStyle {
+    display: *mut xlib::Display,
+    gc:      xlib::GC,
+    text:    Rc<Text>,
+    colors:  [c_ulong; 4],
+    fg:      [xft::XftColor; 2],
+}
+
+impl FrameStyle {
+    /// # Safety
+    /// `display` must be an open connection that outlives the style.
+    pub unsafe fn new( display: *mut xlib::Display, root: xlib::Window,
+                       text: Rc<Text>, colors: [c_ulong; 4] ) -> FrameStyle {
+        let gc = xlib::XCreateGC( display, root, 0, null_mut() );
+        let fg = [ text.color( colors[1] ), text.color( colors[3] ) ];
+
+        FrameStyle {
+            display,
+            gc,
+            text,
+            colors,
+            fg,
+        }
+    }
+
+    pub fn set_colors( &mut self, colors: [c_ulong; 4] ) {
+        self.fg = [ self.text.color( colors[1] ), self.text.color( colors[3] ) ];
+        self.colors = colors;
+    }
+}
+
+impl Drop for FrameStyle {
+    fn drop( &mut self ) {
+        unsafe {
+            xlib::XFreeGC( self.display, self.gc );
+        }
+    }
+}
+
+// The window a client is reparented into in reparenting mode. The client sits
+// below a title bar of config::TITLE_HEIGHT pixels and the border is drawn on
+// the frame instead of the client.
+pub struct Frame {
+    display:   *mut xlib::Display,
+    pub window: xlib::Window,
+    pub depth: i32,
+    canvas:    Canvas,
+}
+
+impl Frame {
+    /// # Safety
+    /// `display` must be an open connection that outlives the frame.
+    pub unsafe fn new( display: *mut xlib::Display, root: xlib::Window,
+                       x: i32, y: i32, w: i32, h: i32, bw: i32 ) -> Frame {
+        let window = xlib::XCreateSimpleWindow( display, root, x, y,
+            w.max( 1 ) as u32, ( h + config::TITLE_HEIGHT ).max( 1 ) as u32, bw as u32, 0, 0 );
+
+        xlib::XSelectInput( display, window,
+            xlib::SubstructureRedirectMask|
+            xlib::SubstructureNotifyMask|
+            xlib::ExposureMask|
+            xlib::ButtonPressMask|
+            xlib::EnterWindowMask );
+
+        Frame {
+            display,
+            window,
+            depth: xlib::XDefaultDepth( display, xlib::XDefaultScreen( display ) ),
+            canvas: Canvas::new( display, window ),
+        }
+    }
+
+    // Which button, if any, is under a point in frame coordinates
+    pub fn button_at( &self, x: i32, y: i32, w: i32 ) -> Option<FrameButton> {
+        let size = config::TITLE_HEIGHT;
+        if y < 0 || y >= size || x >= w {
+            return None;
+        }
+
+        let slot = ( w - 1 - x ) / size;
+        BUTTONS.get( slot as usize ).cloned()
+    }
+
+    pub fn draw( &self, style: &FrameStyle, w: i32, title: &str, focused: bool ) {
+        let size = config::TITLE_HEIGHT;
+        let (bg, fg) = if focused {
+            ( style.colors[2], &style.fg[1] )
+        } else {
+            ( style.colors[0], &style.fg[0] )
+        };
+
+        unsafe {
+            xlib::XSetForeground( self.display, style.gc, bg );
+            xlib::XFillRectangle( self.display, self.window, style.gc, 0, 0, w as u32, size as u32 );
+            xlib::XSetForeground( self.display, style.gc, fg.pixel );
+
+            // Keep the title clear of the buttons
+            let room = w - size * BUTTONS.len() as i32 - 8;
+            if room > 0 {
+                style.text.draw( &self.canvas, 4, ( size - style.text.height() ) / 2, room, title, fg );
+            }
+
+            // Small glyphs inset into square buttons
+            let pad = size / 4;
+            for (i, button) in BUTTONS.iter().enumerate() {
+                let bx = w - size * ( i as i32 + 1 );
+                if bx < 0 {
+                    break;
+                }
+                let (x0, y0, x1, y1) = ( bx + pad, pad, bx + size - pad - 1, size - pad - 1 );
+                match *button {
+                    FrameButton::Close => {
+                        xlib::XDrawLine( self.display, self.window, style.gc, x0, y0, x1, y1 );
+                        xlib::XDrawLine( self.display, self.window, style.gc, x0, y1, x1, y0 );
+                    },
+                    FrameButton::Maximize => {
+                        xlib::XDrawRectangle( self.display, self.window, style.gc,
+                            x0, y0, ( x1 - x0 ) as u32, ( y1 - y0 ) as u32 );
+                    },
+                    FrameButton::Minimize => {
+                        xlib::XDrawLine( self.display, self.window, style.gc, x0, y1, x1, y1 );
+                    },
+                }
+            }
+        }
+    }
+}
+
+impl Drop for Frame {
+    fn drop( &mut self ) {
+        self.canvas.free();
+        unsafe {
+            xlib::XDestroyWindow( self.display, self.window );
+        }
+    }
+}
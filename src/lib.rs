@@ -0,0 +1,12 @@
+extern crate libc;
+extern crate x11;
+
+pub mod client;
+pub mod color;
+pub mod config;
+pub mod frame;
+pub mod outline;
+pub mod palette;
+pub mod switcher;
+pub mod text;
+pub mod windowsystem;
@@ -1,10 +1,6 @@
-extern crate libc;
-extern crate x11;
+extern crate alwm;
 
-pub mod config;
-pub mod windowsystem;
-
-use windowsystem::WindowSystem;
+use alwm::windowsystem::WindowSystem;
 
 #[allow(while_true)]
 fn main() {
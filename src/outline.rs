@@ -0,0 +1,81 @@
+use libc::c_ulong;
+
+use x11::xlib;
+
+// A rectangle outline built from four thin override-redirect windows, so it
+// needs neither a compositor nor the Shape extension
+pub struct Outline {
+    display: *mut xlib::Display,
+    sides:   [xlib::Window; 4],
+    size:    i32,
+    visible: bool,
+}
+
+impl Outline {
+    /// # Safety
+    /// `display` must be an open connection that outlives the outline.
+    pub unsafe fn new( display: *mut xlib::Display, root: xlib::Window, pixel: c_ulong, size: i32 ) -> Outline {
+        let mut sides = [0; 4];
+        for side in sides.iter_mut() {
+            *side = xlib::XCreateSimpleWindow( display, root, 0, 0, 1, 1, 0, 0, pixel );
+
+            let mut wa: xlib::XSetWindowAttributes = ::std::mem::zeroed();
+            wa.override_redirect = 1;
+            xlib::XChangeWindowAttributes( display, *side, xlib::CWOverrideRedirect, &mut wa );
+        }
+
+        Outline {
+            display,
+            sides,
+            size: size.max( 1 ),
+            visible: false,
+        }
+    }
+
+    pub fn show( &mut self, x: i32, y: i32, w: i32, h: i32 ) {
+        let s = self.size;
+        let w = w.max( 2 * s );
+        let h = h.max( 2 * s );
+        let rects = [
+            ( x, y, w, s ),
+            ( x, y + h - s, w, s ),
+            ( x, y, s, h ),
+            ( x + w - s, y, s, h ),
+        ];
+
+        unsafe {
+            for (side, &(rx, ry, rw, rh)) in self.sides.iter().zip( rects.iter() ) {
+                xlib::XMoveResizeWindow( self.display, *side, rx, ry, rw as u32, rh as u32 );
+                if !self.visible {
+                    xlib::XMapRaised( self.display, *side );
+                } else {
+                    xlib::XRaiseWindow( self.display, *side );
+                }
+            }
+        }
+        self.visible = true;
+    }
+
+    pub fn hide( &mut self ) {
+        if !self.visible {
+            return;
+        }
+
+        unsafe {
+            for side in self.sides.iter() {
+                xlib::XUnmapWindow( self.display, *side );
+            }
+        }
+        self.visible = false;
+    }
+}
+
+impl Drop for Outline {
+    fn drop( &mut self ) {
+        unsafe {
+            for side in self.sides.iter() {
+                xlib::XDestroyWindow( self.display, *side );
+            }
+        }
+    }
+}
@@ -0,0 +1,92 @@
+use libc::{c_int, c_ulong};
+
+use x11::xlib;
+use color::Color;
+use config;
+
+// Pixel values for every colour the window manager draws with. They are
+// allocated once from the default colormap, so drawing never has to talk to
+// the colormap, and freed again by WindowSystem::reload_palette.
+#[derive(Default)]
+pub struct Palette {
+    pub focus:    Vec<c_ulong>,
+    pub unfocus:  Vec<c_ulong>,
+    pub urgent:   Vec<c_ulong>,
+    pub outline:  c_ulong,
+    pub switcher: [c_ulong; 4],
+    pub title:    [c_ulong; 4],
+    allocated:    Vec<c_ulong>,
+}
+
+impl Palette {
+    /// # Safety
+    /// `display` must be an open connection.
+    pub unsafe fn new( display: *mut xlib::Display ) -> Palette {
+        let mut allocated = Vec::new();
+
+        let fallback = match Color::parse( config::FALLBACK_COLOR ).and_then( |c| c.alloc( display ) ) {
+            Ok(pixel) => {
+                allocated.push( pixel );
+                pixel
+            },
+            Err(e) => {
+                eprintln!( "alwm: fallback colour: {}, using white", e );
+                xlib::XWhitePixel( display, xlib::XDefaultScreen( display ) )
+            },
+        };
+
+        // Bad colours are reported and replaced rather than taking us down
+        let mut alloc = |name: &str| {
+            match Color::parse( name ).and_then( |c| c.alloc( display ) ) {
+                Ok(pixel) => {
+                    allocated.push( pixel );
+                    pixel
+                },
+                Err(e) => {
+                    eprintln!( "alwm: {}, using {}", e, config::FALLBACK_COLOR );
+                    fallback
+                },
+            }
+        };
+
+        let focus = config::FOCUS_BORDERS.iter().map( |b| alloc( b.color ) ).collect();
+        let unfocus = config::UNFOCUSED_BORDERS.iter().map( |b| alloc( b.color ) ).collect();
+        let urgent = config::URGENT_BORDERS.iter().map( |b| alloc( b.color ) ).collect();
+        let outline = alloc( config::SNAP_OUTLINE.color );
+        let switcher = [
+            alloc( config::SWITCHER_NORM[0] ),
+            alloc( config::SWITCHER_NORM[1] ),
+            alloc( config::SWITCHER_SEL[0] ),
+            alloc( config::SWITCHER_SEL[1] ),
+        ];
+
+        let title = [
+            alloc( config::TITLE_NORM[0] ),
+            alloc( config::TITLE_NORM[1] ),
+            alloc( config::TITLE_SEL[0] ),
+            alloc( config::TITLE_SEL[1] ),
+        ];
+
+        Palette {
+            focus,
+            unfocus,
+            urgent,
+            outline,
+            switcher,
+            title,
+            allocated,
+        }
+    }
+
+    /// # Safety
+    /// `display` must be the connection the palette was allocated on.
+    pub unsafe fn free( &mut self, display: *mut xlib::Display ) {
+        if self.allocated.is_empty() {
+            return;
+        }
+
+        let cmap = xlib::XDefaultColormap( display, xlib::XDefaultScreen( display ) );
+        xlib::XFreeColors( display, cmap, self.allocated.as_mut_ptr(), self.allocated.len() as c_int, 0 );
+        self.allocated.clear();
+    }
+}
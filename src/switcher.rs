@@ -0,0 +1,204 @@
+use libc::{c_char, c_int, c_ulong};
+use std::ptr::null_mut;
+use std::rc::Rc;
+
+use x11::{keysym, xft, xlib};
+use config;
+use text::{Canvas, Text};
+
+// One line in the switcher
+pub struct Entry {
+    pub window: xlib::Window,
+    pub title: String,
+    pub class: String,
+}
+
+pub enum SwitcherEvent {
+    Continue,
+    Cancel,
+    Select( xlib::Window ),
+}
+
+// Popup listing managed windows. Typing filters the list by title or class,
+// Up/Down or Tab move the selection, Return picks and Escape cancels.
+pub struct Switcher {
+    display:  *mut xlib::Display,
+    window:   xlib::Window,
+    gc:       xlib::GC,
+    canvas:   Canvas,
+    text:     Rc<Text>,
+    colors:   [c_ulong; 4],
+    fg:       [xft::XftColor; 2],
+    entries:  Vec<Entry>,
+    filter:   String,
+    selected: usize,
+    row_h:    i32,
+    width:    u32,
+}
+
+impl Switcher {
+    /// # Safety
+    /// `display` must be an open connection that outlives the switcher.
+    /// `colors` are the normal background and foreground followed by the
+    /// selected ones. The popup is centred on `monitor`.
+    pub unsafe fn new( display: *mut xlib::Display, root: xlib::Window, text: Rc<Text>,
+                       entries: Vec<Entry>, colors: [c_ulong; 4],
+                       (mx, my, mw, mh): (i32, i32, i32, i32) ) -> Switcher {
+        let row_h = text.height() + 4;
+        let fg = [ text.color( colors[1] ), text.color( colors[3] ) ];
+        let rows = entries.len().min( config::SWITCHER_ROWS ) as i32 + 1;
+
+        let width = config::SWITCHER_WIDTH.min( mw.max( 1 ) as u32 );
+        let height = ( rows * row_h ) as u32;
+
+        let window = xlib::XCreateSimpleWindow( display, root,
+            mx + ( mw - width as i32 ) / 2, my + ( mh - height as i32 ) / 2, width, height,
+            0, 0, colors[0] );
+
+        let mut wa: xlib::XSetWindowAttributes = ::std::mem::zeroed();
+        wa.override_redirect = 1;
+        wa.event_mask = xlib::ExposureMask;
+        xlib::XChangeWindowAttributes( display, window,
+            xlib::CWOverrideRedirect|xlib::CWEventMask, &mut wa );
+
+        let gc = xlib::XCreateGC( display, window, 0, null_mut() );
+
+        xlib::XMapRaised( display, window );
+
+        Switcher {
+            display,
+            window,
+            gc,
+            canvas: Canvas::new( display, window ),
+            text,
+            colors,
+            fg,
+            entries,
+            filter: String::new(),
+            selected: 0,
+            row_h,
+            width,
+        }
+    }
+
+    pub fn window( &self ) -> xlib::Window {
+        self.window
+    }
+
+    pub fn on_key( &mut self, event: &mut xlib::XKeyEvent ) -> SwitcherEvent {
+        let mut buf = [0 as c_char; 32];
+        let mut keysym: c_ulong = 0;
+        let len = unsafe {
+            xlib::XLookupString( event, buf.as_mut_ptr(), buf.len() as c_int, &mut keysym, null_mut() )
+        };
+        let count = self.matches().len();
+
+        match keysym as u32 {
+            keysym::XK_Escape => return SwitcherEvent::Cancel,
+            keysym::XK_Return | keysym::XK_KP_Enter => {
+                return match self.matches().get( self.selected ) {
+                    Some(entry) => SwitcherEvent::Select( entry.window ),
+                    None => SwitcherEvent::Cancel,
+                };
+            },
+            keysym::XK_Down | keysym::XK_Tab => {
+                if count > 0 {
+                    self.selected = ( self.selected + 1 ) % count;
+                }
+            },
+            keysym::XK_Up | keysym::XK_ISO_Left_Tab => {
+                if count > 0 {
+                    self.selected = ( self.selected + count - 1 ) % count;
+                }
+            },
+            keysym::XK_BackSpace => {
+                self.filter.pop();
+                self.selected = 0;
+            },
+            _ => {
+                let text: Vec<u8> = buf[.. len.max( 0 ) as usize].iter().map( |&c| c as u8 ).collect();
+                let text = String::from_utf8_lossy( &text );
+                if text.chars().all( |c| !c.is_control() ) && !text.is_empty() {
+                    self.filter.push_str( &text );
+                    self.selected = 0;
+                }
+            },
+        }
+
+        self.draw();
+        SwitcherEvent::Continue
+    }
+
+    // Forget a window that went away while the switcher was open
+    pub fn remove( &mut self, window: xlib::Window ) {
+        if !self.entries.iter().any( |e| e.window == window ) {
+            return;
+        }
+        self.entries.retain( |e| e.window != window );
+
+        let count = self.matches().len();
+        if self.selected >= count {
+            self.selected = count.saturating_sub( 1 );
+        }
+        self.draw();
+    }
+
+    // Entries whose title or class contains the filter, ignoring case
+    fn matches( &self ) -> Vec<&Entry> {
+        let filter = self.filter.to_lowercase();
+        self.entries.iter()
+            .filter( |e| e.title.to_lowercase().contains( &filter ) ||
+                         e.class.to_lowercase().contains( &filter ) )
+            .collect()
+    }
+
+    pub fn draw( &self ) {
+        let matches = self.matches();
+        let rows = self.entries.len().min( config::SWITCHER_ROWS ) + 1;
+
+        // Keep the selection on screen by scrolling the list
+        let first = if self.selected >= config::SWITCHER_ROWS {
+            self.selected + 1 - config::SWITCHER_ROWS
+        } else {
+            0
+        };
+
+        unsafe {
+            xlib::XSetForeground( self.display, self.gc, self.colors[0] );
+            xlib::XFillRectangle( self.display, self.window, self.gc, 0, 0,
+                self.width, ( rows as i32 * self.row_h ) as u32 );
+
+            self.draw_text( 0, &format!( "> {}", self.filter ), &self.fg[0] );
+
+            for (row, entry) in matches.iter().enumerate().skip( first ).take( config::SWITCHER_ROWS ) {
+                let y = ( row - first + 1 ) as i32 * self.row_h;
+                let fg = if row == self.selected {
+                    xlib::XSetForeground( self.display, self.gc, self.colors[2] );
+                    xlib::XFillRectangle( self.display, self.window, self.gc, 0, y,
+                        self.width, self.row_h as u32 );
+                    &self.fg[1]
+                } else {
+                    &self.fg[0]
+                };
+                self.draw_text( y, &format!( "{}  [{}]", entry.title, entry.class ), fg );
+            }
+
+            xlib::XFlush( self.display );
+        }
+    }
+
+    fn draw_text( &self, y: i32, text: &str, color: &xft::XftColor ) {
+        self.text.draw( &self.canvas, 4, y + 2, self.width as i32 - 8, text, color );
+    }
+}
+
+impl Drop for Switcher {
+    fn drop( &mut self ) {
+        self.canvas.free();
+        unsafe {
+            xlib::XFreeGC( self.display, self.gc );
+            xlib::XDestroyWindow( self.display, self.window );
+            xlib::XFlush( self.display );
+        }
+    }
+}
@@ -0,0 +1,223 @@
+use libc::{c_int, c_uchar, c_ulong};
+use std::ffi::CString;
+
+use x11::{xft, xlib, xrender};
+
+// An XftDraw kept for the life of a window so redraws do not create and
+// destroy one each time. Call free before destroying the window.
+pub struct Canvas {
+    draw: *mut xft::XftDraw,
+}
+
+impl Canvas {
+    /// # Safety
+    /// `display` must be an open connection and `drawable` use the default
+    /// visual and colormap.
+    pub unsafe fn new( display: *mut xlib::Display, drawable: xlib::Drawable ) -> Canvas {
+        let screen = xlib::XDefaultScreen( display );
+        Canvas {
+            draw: xft::XftDrawCreate( display, drawable,
+                xlib::XDefaultVisual( display, screen ), xlib::XDefaultColormap( display, screen ) ),
+        }
+    }
+
+    pub fn free( &mut self ) {
+        if !self.draw.is_null() {
+            unsafe {
+                xft::XftDrawDestroy( self.draw );
+            }
+            self.draw = ::std::ptr::null_mut();
+        }
+    }
+}
+
+impl Drop for Canvas {
+    fn drop( &mut self ) {
+        self.free();
+    }
+}
+
+// Anti-aliased UTF-8 text through Xft, shared by everything alwm draws.
+// Fonts are fontconfig names tried in order for each character, so later
+// fonts fill in glyphs the first one lacks.
+pub struct Text {
+    display: *mut xlib::Display,
+    fonts:   Vec<*mut xft::XftFont>,
+    pub ascent:  i32,
+    pub descent: i32,
+}
+
+impl Text {
+    /// # Safety
+    /// `display` must be an open connection that outlives the text.
+    pub unsafe fn new( display: *mut xlib::Display, names: &[&str] ) -> Text {
+        let screen = xlib::XDefaultScreen( display );
+        let mut fonts = Vec::new();
+
+        for name in names.iter() {
+            let font = match CString::new( *name ) {
+                Ok(cname) => xft::XftFontOpenName( display, screen, cname.as_ptr() ),
+                Err(_) => ::std::ptr::null_mut(),
+            };
+            if font.is_null() {
+                eprintln!( "alwm: cannot load font {:?}", name );
+            } else {
+                fonts.push( font );
+            }
+        }
+
+        // Fontconfig always has something for "monospace"
+        if fonts.is_empty() {
+            let fallback = CString::new( "monospace" ).unwrap();
+            let font = xft::XftFontOpenName( display, screen, fallback.as_ptr() );
+            if !font.is_null() {
+                fonts.push( font );
+            }
+        }
+
+        let (ascent, descent) = match fonts.first() {
+            Some(&font) => ( (*font).ascent, (*font).descent ),
+            None => ( 12, 2 ),
+        };
+
+        Text {
+            display,
+            fonts,
+            ascent,
+            descent,
+        }
+    }
+
+    pub fn height( &self ) -> i32 {
+        self.ascent + self.descent
+    }
+
+    // Width in pixels the text takes when drawn
+    pub fn width( &self, text: &str ) -> i32 {
+        self.runs( text ).iter()
+            .map( |&(font, run)| unsafe { self.run_width( font, run ) } )
+            .sum()
+    }
+
+    // Cut text down to a pixel width, ending in an ellipsis when anything had
+    // to go. Each character is measured once.
+    pub fn ellipsize( &self, text: &str, width: i32 ) -> String {
+        // Without a font nothing has a width
+        if self.fonts.is_empty() {
+            return text.to_string();
+        }
+
+        let advances: Vec<(usize, i32)> = text.char_indices()
+            .map( |(i, ch)| {
+                let end = i + ch.len_utf8();
+                ( end, unsafe { self.run_width( self.font_for( ch ), &text[i .. end] ) } )
+            } )
+            .collect();
+        if advances.iter().map( |&(_, w)| w ).sum::<i32>() <= width {
+            return text.to_string();
+        }
+
+        let room = width - self.width( "…" );
+        if room < 0 {
+            return String::new();
+        }
+
+        let mut used = 0;
+        let mut cut = 0;
+        for &(end, advance) in advances.iter() {
+            if used + advance > room {
+                break;
+            }
+            used += advance;
+            cut = end;
+        }
+        format!( "{}…", &text[.. cut] )
+    }
+
+    // Xft colour for a pixel from the default colormap
+    pub fn color( &self, pixel: c_ulong ) -> xft::XftColor {
+        unsafe {
+            let cmap = xlib::XDefaultColormap( self.display, xlib::XDefaultScreen( self.display ) );
+            let mut color: xlib::XColor = ::std::mem::zeroed();
+            color.pixel = pixel;
+            xlib::XQueryColor( self.display, cmap, &mut color );
+
+            xft::XftColor {
+                pixel,
+                color: xrender::XRenderColor {
+                    red: color.red,
+                    green: color.green,
+                    blue: color.blue,
+                    alpha: 0xffff,
+                },
+            }
+        }
+    }
+
+    // Draw text with its top left corner at x, y, clipped to width
+    pub fn draw( &self, canvas: &Canvas, x: i32, y: i32, width: i32,
+                 text: &str, color: &xft::XftColor ) {
+        if canvas.draw.is_null() {
+            return;
+        }
+        let text = self.ellipsize( text, width );
+
+        let mut pen = x;
+        for (font, run) in self.runs( &text ) {
+            unsafe {
+                xft::XftDrawStringUtf8( canvas.draw, color, font, pen, y + self.ascent,
+                    run.as_ptr() as *const c_uchar, run.len() as c_int );
+                pen += self.run_width( font, run );
+            }
+        }
+    }
+
+    // Split text into pieces that each come from a single font, taking the
+    // first font that has a glyph for each character
+    fn runs<'a>( &self, text: &'a str ) -> Vec<(*mut xft::XftFont, &'a str)> {
+        let mut runs = Vec::new();
+        let first = match self.fonts.first() {
+            Some(&font) => font,
+            None => return runs,
+        };
+
+        let mut start = 0;
+        let mut current = first;
+        for (i, ch) in text.char_indices() {
+            let font = self.font_for( ch );
+            if font != current && i > start {
+                runs.push( ( current, &text[start .. i] ) );
+                start = i;
+            }
+            current = font;
+        }
+        if start < text.len() {
+            runs.push( ( current, &text[start ..] ) );
+        }
+        runs
+    }
+
+    // The first font with a glyph for the character, or the first font.
+    // There has to be at least one.
+    fn font_for( &self, ch: char ) -> *mut xft::XftFont {
+        self.fonts.iter().cloned()
+            .find( |&f| unsafe { xft::XftCharExists( self.display, f, ch as u32 ) != 0 } )
+            .unwrap_or_else( || self.fonts[0] )
+    }
+
+    unsafe fn run_width( &self, font: *mut xft::XftFont, run: &str ) -> i32 {
+        let mut extents: xrender::XGlyphInfo = ::std::mem::zeroed();
+        xft::XftTextExtentsUtf8( self.display, font, run.as_ptr() as *const c_uchar, run.len() as c_int, &mut extents );
+        extents.xOff as i32
+    }
+}
+
+impl Drop for Text {
+    fn drop( &mut self ) {
+        unsafe {
+            for &font in self.fonts.iter() {
+                xft::XftFontClose( self.display, font );
+            }
+        }
+    }
+}
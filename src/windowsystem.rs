@@ -1,39 +1,271 @@
 use std::thread::spawn;
-use std::process::Command;
-use std::num::Wrapping;
+use std::fs;
+use std::io::Write;
+use std::process::{Command, Stdio};
 use std::cmp::max;
-use libc::{c_uchar,c_int, c_ulong};
-use std::ffi::CString;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+use libc;
+use libc::{c_uchar,c_int, c_long, c_ulong};
+use std::ffi::{CStr, CString};
 use std::ptr::{
   null,
   null_mut,
 };
 
-use x11::xlib;
+use x11::{keysym, xinerama, xlib};
 use config;
 use config::KeyCmd;
+use client::Client;
+use frame::{Frame, FrameButton, FrameStyle};
+use outline::Outline;
+use palette::Palette;
+use switcher::{Entry, Switcher, SwitcherEvent};
+use text::Text;
 
 unsafe extern fn error_handler(_: *mut xlib::Display, _: *mut xlib::XErrorEvent) -> c_int {
     return 0;
 }
 
+// Where a rectangle given relative to the outer corner of a border lands in
+// a border pixmap. X tiles the border from the inner corner of the window, so
+// the outer corner sits at (-bw, -bw) and wraps around to the far side of
+// the pixmap. Rectangles are split where they cross that seam.
+fn tile_rects( bw: i32, (x, y, w, h): (i32, i32, i32, i32), (pw, ph): (i32, i32) ) -> Vec<(i32, i32, i32, i32)> {
+    if w <= 0 || h <= 0 {
+        return Vec::new();
+    }
+
+    let split = |start: i32, len: i32, size: i32| -> Vec<(i32, i32)> {
+        let start = start - bw;
+        if start < 0 {
+            vec![ ( start + size, len.min( -start ) ), ( 0, len + start ) ]
+        } else {
+            vec![ ( start, len ) ]
+        }
+    };
+
+    let mut rects = Vec::new();
+    for &(sx, sw) in split( x, w, pw ).iter().filter( |&&(_, len)| len > 0 ) {
+        for &(sy, sh) in split( y, h, ph ).iter().filter( |&&(_, len)| len > 0 ) {
+            rects.push( ( sx, sy, sw, sh ) );
+        }
+    }
+    rects
+}
+
+unsafe fn fill_tiled( display: *mut xlib::Display, pixmap: xlib::Pixmap, gc: xlib::GC, bw: i32,
+                      rect: (i32, i32, i32, i32), size: (i32, i32) ) {
+    for &(x, y, w, h) in tile_rects( bw, rect, size ).iter() {
+        xlib::XFillRectangle( display, pixmap, gc, x, y, w as u32, h as u32 );
+    }
+}
+
+// What is left of a monitor after the struts. Struts are measured from the
+// edges of the whole screen and only count on the monitors their start and
+// end reach.
+fn free_area( (mx, my, mw, mh): (i32, i32, i32, i32), (sw, sh): (i32, i32),
+              struts: &[[i32; 12]] ) -> (i32, i32, i32, i32) {
+    let overlaps = |start: i32, end: i32, low: i32, len: i32| start < low + len && end >= low;
+
+    let (mut x0, mut y0, mut x1, mut y1) = ( mx, my, mx + mw, my + mh );
+    for s in struts.iter() {
+        if s[0] > 0 && overlaps( s[4], s[5], my, mh ) {
+            x0 = x0.max( s[0] );
+        }
+        if s[1] > 0 && overlaps( s[6], s[7], my, mh ) {
+            x1 = x1.min( sw - s[1] );
+        }
+        if s[2] > 0 && overlaps( s[8], s[9], mx, mw ) {
+            y0 = y0.max( s[2] );
+        }
+        if s[3] > 0 && overlaps( s[10], s[11], mx, mw ) {
+            y1 = y1.min( sh - s[3] );
+        }
+    }
+
+    // A strut that eats the whole monitor is not worth honouring
+    if x1 <= x0 || y1 <= y0 {
+        return ( mx, my, mw, mh );
+    }
+    ( x0, y0, x1 - x0, y1 - y0 )
+}
+
+// X timestamps are 32 bit milliseconds and wrap around
+fn time_before( a: c_ulong, b: c_ulong ) -> bool {
+    ( ( a as u32 ).wrapping_sub( b as u32 ) as i32 ) < 0
+}
+
+// Parent of a process from /proc/<pid>/stat. The command name in there can
+// hold spaces and parentheses, so fields are counted from the last ')'.
+fn parent_pid( pid: u32 ) -> Option<u32> {
+    let stat = fs::read_to_string( format!( "/proc/{}/stat", pid ) ).ok()?;
+    let rest = &stat[stat.rfind( ')' )? + 1 ..];
+    rest.split_whitespace().nth( 1 )?.parse().ok()
+}
+
+// Start a program given as its arguments, without waiting for it
+fn run_command( command: &[&str] ) {
+    let command: Vec<String> = command.iter().map( |arg| arg.to_string() ).collect();
+    if command.is_empty() {
+        return;
+    }
+    spawn(move || {
+        if let Err(e) = Command::new( &command[0] ).args( &command[1..] ).spawn() {
+            eprintln!( "alwm: cannot run {}: {}", command[0], e );
+        }
+    });
+}
+
+// Ask the running window manager to restore a minimized window. This goes
+// through its own connection so it can be called from any thread.
+fn send_restore( window: xlib::Window ) {
+    unsafe {
+        let display = xlib::XOpenDisplay( null() );
+        if display.is_null() {
+            return;
+        }
+
+        let root = xlib::XDefaultRootWindow( display );
+        let name = CString::new( "_ALWM_RESTORE" ).unwrap();
+        let mut data = xlib::ClientMessageData::new();
+        data.set_long( 0, window as c_long );
+        let message = xlib::XClientMessageEvent {
+            type_: xlib::ClientMessage,
+            serial: 0,
+            send_event: 1,
+            display,
+            window: root,
+            message_type: xlib::XInternAtom( display, name.as_ptr(), 0 ),
+            format: 32,
+            data,
+        };
+
+        let mut event = xlib::XEvent::from( message );
+        xlib::XSendEvent( display, root, 0, xlib::SubstructureRedirectMask|xlib::SubstructureNotifyMask, &mut event );
+        xlib::XCloseDisplay( display );
+    }
+}
+
+// WM_STATE values from the ICCCM
+const WITHDRAWN_STATE: c_int = 0;
+const NORMAL_STATE: c_int = 1;
+const ICONIC_STATE: c_int = 3;
+
+// Glyphs from the X cursor font
+const XC_BOTTOM_LEFT_CORNER: u32 = 12;
+const XC_BOTTOM_RIGHT_CORNER: u32 = 14;
+const XC_BOTTOM_SIDE: u32 = 16;
+const XC_FLEUR: u32 = 52;
+const XC_LEFT_SIDE: u32 = 70;
+const XC_RIGHT_SIDE: u32 = 96;
+const XC_TOP_LEFT_CORNER: u32 = 134;
+const XC_TOP_RIGHT_CORNER: u32 = 136;
+const XC_TOP_SIDE: u32 = 138;
+
+// Which edge of a window a point is nearest to, as -1, 0 or 1 per axis. The
+// outer thirds pick that side, and a point in the middle picks the nearest
+// corner.
+fn nearest_edge( x: i32, y: i32, w: i32, h: i32, px: i32, py: i32 ) -> (i32, i32) {
+    let side = |p: i32, start: i32, len: i32| {
+        if p < start + len / 3 { -1 } else if p >= start + len - len / 3 { 1 } else { 0 }
+    };
+    let half = |p: i32, start: i32, len: i32| if p < start + len / 2 { -1 } else { 1 };
+
+    match ( side( px, x, w ), side( py, y, h ) ) {
+        (0, 0) => ( half( px, x, w ), half( py, y, h ) ),
+        edge => edge,
+    }
+}
+
+fn resize_cursor( edge: (i32, i32) ) -> u32 {
+    match edge {
+        (-1, -1) => XC_TOP_LEFT_CORNER,
+        (1, -1) => XC_TOP_RIGHT_CORNER,
+        (-1, 1) => XC_BOTTOM_LEFT_CORNER,
+        (-1, 0) => XC_LEFT_SIDE,
+        (1, 0) => XC_RIGHT_SIDE,
+        (0, -1) => XC_TOP_SIDE,
+        (0, 1) => XC_BOTTOM_SIDE,
+        _ => XC_BOTTOM_RIGHT_CORNER,
+    }
+}
+
+// Keyboard resize step, rounded to whole size increments for terminals
+fn resize_step( inc: i32 ) -> i32 {
+    if inc > 1 {
+        max( 1, config::RESIZE_STEP / inc ) * inc
+    } else {
+        config::RESIZE_STEP
+    }
+}
+
+// Shift, Control, Super and friends, as well as the lock and level keys
+fn is_modifier_key( keysym: c_ulong ) -> bool {
+    let keysym = keysym as u32;
+    ( keysym::XK_Shift_L ..= keysym::XK_Hyper_R ).contains( &keysym ) ||
+    ( keysym::XK_ISO_Lock ..= keysym::XK_ISO_Level5_Lock ).contains( &keysym ) ||
+    keysym == keysym::XK_Mode_switch ||
+    keysym == keysym::XK_Num_Lock
+}
+
+// An Alt-Tab style walk through the focus history. The order is frozen when
+// the walk starts and only committed once the modifier is released.
+struct FocusCycle {
+    order: Vec<xlib::Window>,
+    index: usize,
+    mask:  u32,
+}
+
 pub struct WindowSystem {
     display:    *mut xlib::Display,
     root:       xlib::Window,
     x:          i32,
     y:          i32,
-    w:          u32,
-    h:          u32,
     button_id:  u32,
+    drag_geom:  (i32, i32, i32, i32),
+    drag_edge:  (i32, i32),
+    drag_cursor: xlib::Cursor,
+    drag_window: xlib::Window,
+    wireframe:  Option<(i32, i32, i32, i32)>,
+    xor_gc:     xlib::GC,
+    snap_target: Option<(i32, i32, i32, i32)>,
+    outline:    Option<Outline>,
+    monitors:   Vec<(i32, i32, i32, i32)>,
+    palette:    Palette,
+    border_gcs: Vec<(i32, xlib::GC)>,
     borderinfo: config::BorderInfo,
     focuswin:   xlib::Window,
+    numlockmask:    u32,
+    scrolllockmask: u32,
+    keymaps:    Vec<usize>,
+    keymap_deadline: Option<Instant>,
+    // Focus and raise waiting out FOCUS_DELAY and RAISE_DELAY
+    pending_focus: Option<(xlib::Window, Instant)>,
+    pending_raise: Option<(xlib::Window, Instant)>,
+    clients:    Vec<Client>,
+    focus_history: Vec<xlib::Window>,
+    urgent:     Vec<xlib::Window>,
+    iconified:  Vec<xlib::Window>,
+    last_input: c_ulong,
+    // Newest server timestamp seen in an event, for messages that must not
+    // carry CurrentTime
+    last_time:  c_ulong,
+    focus_serial: c_ulong,
+    cycle:      Option<FocusCycle>,
+    switcher:   Option<Switcher>,
+    frame_style: Option<FrameStyle>,
+    text:       Rc<Text>,
+    // _NET_SUPPORTING_WM_CHECK window, focused when no client is
+    support:    xlib::Window,
 }
 
 impl WindowSystem {
     pub fn new() -> WindowSystem {
         use x11::xlib::*;
 
-        let borderinfo = config::BorderInfo::new( config::FOCUS_BORDERS, config::UNFOCUSED_BORDERS );
+        let borderinfo = config::BorderInfo::new( config::FOCUS_BORDERS, config::UNFOCUSED_BORDERS,
+                                                  config::URGENT_BORDERS );
 
         unsafe {
             // Open display
@@ -48,16 +280,43 @@ impl WindowSystem {
 
             xlib::XSetErrorHandler(Some(error_handler));
 
-            let ws = WindowSystem {
+            let mut ws = WindowSystem {
                 display: display,
                 root: root,
                 x: 0,
                 y: 0,
-                w: 0,
-                h: 0,
                 button_id: 0,
+                drag_geom: (0, 0, 0, 0),
+                drag_edge: (1, 1),
+                drag_cursor: 0,
+                drag_window: 0,
+                wireframe: None,
+                xor_gc: null_mut(),
+                snap_target: None,
+                outline: None,
+                monitors: Vec::new(),
+                palette: Palette::default(),
+                border_gcs: Vec::new(),
                 borderinfo: borderinfo,
                 focuswin: root,
+                numlockmask: 0,
+                scrolllockmask: 0,
+                keymaps: Vec::new(),
+                keymap_deadline: None,
+                pending_focus: None,
+                pending_raise: None,
+                clients: Vec::new(),
+                focus_history: Vec::new(),
+                urgent: Vec::new(),
+                iconified: Vec::new(),
+                last_input: 0,
+                last_time: 0,
+                focus_serial: 0,
+                cycle: None,
+                switcher: None,
+                frame_style: None,
+                text: Rc::new( Text::new( display, config::FONTS ) ),
+                support: 0,
             };
 
             let mut wa = XSetWindowAttributes {
@@ -81,6 +340,7 @@ impl WindowSystem {
                             StructureNotifyMask|
                             ButtonPressMask|
                             ButtonReleaseMask|
+                            EnterWindowMask|
                             PropertyChangeMask,
             };
 
@@ -90,50 +350,138 @@ impl WindowSystem {
             xlib::XSync( ws.display, 0 );
             xlib::XUngrabButton(ws.display, 0, 0x8000, ws.root);
 
-            let name = (*CString::new(&b"ALWM"[..]).unwrap()).as_ptr();
+            let name = b"ALWM";
 
             let wmcheck = ws.get_atom("_NET_SUPPORTING_WM_CHECK");
             let wmname = ws.get_atom("_NET_WM_NAME");
             let utf8 = ws.get_atom("UTF8_STRING");
-            let xa_window = ws.get_atom("xlib::XA_WINDOW");
 
-            let mut root_cpy = ws.root;
-            let root_ptr : *mut Window = &mut root_cpy;
-            xlib::XChangeProperty(ws.display, ws.root, wmcheck, xa_window, 32, 0, root_ptr as *mut c_uchar, 1);
-            xlib::XChangeProperty(ws.display, ws.root, wmname, utf8, 8, 0, name as *mut c_uchar, 5);
+            // Mapped off screen so it can hold the focus when no client does
+            ws.support = xlib::XCreateSimpleWindow( ws.display, ws.root, -10, -10, 1, 1, 0, 0, 0 );
+            let mut swa: XSetWindowAttributes = ::std::mem::zeroed();
+            swa.override_redirect = 1;
+            swa.event_mask = PropertyChangeMask;
+            XChangeWindowAttributes( ws.display, ws.support, CWOverrideRedirect | CWEventMask, &mut swa );
+            xlib::XMapWindow( ws.display, ws.support );
+
+            let mut support = ws.support;
+            let support_ptr : *mut Window = &mut support;
+            for &window in [ ws.root, ws.support ].iter() {
+                xlib::XChangeProperty(ws.display, window, wmcheck, xlib::XA_WINDOW, 32, 0, support_ptr as *mut c_uchar, 1);
+                xlib::XChangeProperty(ws.display, window, wmname, utf8, 8, 0, name.as_ptr(), name.len() as i32);
+            }
+
+            // Writing those properties gives the first server timestamp,
+            // before any other event has brought one
+            let mut ev = xlib::XEvent { pad : [0; 24] };
+            xlib::XWindowEvent( ws.display, ws.support, PropertyChangeMask, &mut ev );
+            ws.note_time( &ev );
+            ws.focus_fallback( xlib::CurrentTime );
+            ws.announce_mode();
+            ws.announce_urgent();
+            ws.update_monitors();
+            ws.reload_palette();
+            if config::REPARENT {
+                ws.frame_style = Some( FrameStyle::new( ws.display, ws.root, ws.text.clone(), ws.palette.title ) );
+            }
 
             ws
         }
     }
 
-    pub fn grab_keys(&self) {
+    pub fn grab_keys(&mut self) {
+        self.update_lock_masks();
         unsafe {
-            // Grab keys
-            // Exit key behavior
-            let kc_exit = xlib::XKeysymToKeycode( self.display, KeyCmd::get_keysym( config::EXIT_KEY ) );
-            xlib::XGrabKey( self.display, kc_exit as i32, KeyCmd::get_modifier( config::EXIT_KEY ),
-                self.root as c_ulong, 1, xlib::GrabModeAsync, xlib::GrabModeAsync );
-            let kc_term = xlib::XKeysymToKeycode( self.display, KeyCmd::get_keysym( config::TERM_KEY ) );
-            xlib::XGrabKey( self.display, kc_term as i32, KeyCmd::get_modifier( config::TERM_KEY ),
-                self.root as c_ulong, 1, xlib::GrabModeAsync, xlib::GrabModeAsync );
-            let kc_run = xlib::XKeysymToKeycode( self.display, KeyCmd::get_keysym( config::RUN_KEY ) );
-            xlib::XGrabKey( self.display, kc_run as i32, KeyCmd::get_modifier( config::RUN_KEY ),
-                self.root as c_ulong, 1, xlib::GrabModeAsync, xlib::GrabModeAsync );
+            xlib::XUngrabKey( self.display, xlib::AnyKey, xlib::AnyModifier, self.root );
+        }
+
+        // Grab keys
+        for bind in config::KEYS.iter() {
+            self.grab_key( bind.key );
         }
     }
 
     pub fn grab_buttons(&self) {
         unsafe {
-            // Grab mouse
-            xlib::XGrabButton( self.display, config::MOUSE_MOVE.button, config::MOUSE_MOVE.modifier,
-                self.root, 1, xlib::ButtonPressMask as u32, xlib::GrabModeAsync, xlib::GrabModeAsync, 0, 0 );
-            xlib::XGrabButton( self.display, config::MOUSE_RESIZE.button, config::MOUSE_RESIZE.modifier,
-                self.root, 1, xlib::ButtonPressMask as u32, xlib::GrabModeAsync, xlib::GrabModeAsync, 0, 0 );
-            xlib::XGrabButton( self.display, config::MOUSE_RAISE.button, config::MOUSE_RAISE.modifier,
-                self.root, 1, xlib::ButtonPressMask as u32, xlib::GrabModeAsync, xlib::GrabModeAsync, 0, 0 );
+            xlib::XUngrabButton( self.display, xlib::AnyButton as u32, xlib::AnyModifier, self.root );
+        }
+
+        // Grab mouse
+        self.grab_button( config::MOUSE_MOVE );
+        self.grab_button( config::MOUSE_RESIZE );
+        self.grab_button( config::MOUSE_RAISE );
+    }
+
+    // Grab a key once for every combination of lock modifiers so bindings
+    // keep working with NumLock, CapsLock or ScrollLock on.
+    fn grab_key( &self, key: KeyCmd ) {
+        let modifier = KeyCmd::get_modifier( key );
+        unsafe {
+            let keycode = xlib::XKeysymToKeycode( self.display, KeyCmd::get_keysym( key ) );
+            if keycode == 0 { return; }
+
+            for lock in self.lock_masks().iter() {
+                xlib::XGrabKey( self.display, keycode as i32, modifier | lock,
+                    self.root as c_ulong, 1, xlib::GrabModeAsync, xlib::GrabModeAsync );
+            }
+        }
+    }
+
+    fn grab_button( &self, button: config::MouseCmd ) {
+        unsafe {
+            for lock in self.lock_masks().iter() {
+                xlib::XGrabButton( self.display, button.button, button.modifier | lock,
+                    self.root, 1, xlib::ButtonPressMask as u32, xlib::GrabModeAsync, xlib::GrabModeAsync, 0, 0 );
+            }
+        }
+    }
+
+    // Find which modifier bits NumLock and ScrollLock are mapped to. These
+    // differ between keyboard setups so they have to be looked up at runtime.
+    fn update_lock_masks( &mut self ) {
+        self.numlockmask = 0;
+        self.scrolllockmask = 0;
+
+        unsafe {
+            let numlock = xlib::XKeysymToKeycode( self.display, keysym::XK_Num_Lock as c_ulong );
+            let scrolllock = xlib::XKeysymToKeycode( self.display, keysym::XK_Scroll_Lock as c_ulong );
+
+            let modmap = xlib::XGetModifierMapping( self.display );
+            if modmap.is_null() { return; }
+
+            let per_mod = (*modmap).max_keypermod as usize;
+            for i in 0 .. 8 {
+                for j in 0 .. per_mod {
+                    let keycode = *(*modmap).modifiermap.add( i * per_mod + j );
+                    if keycode == 0 { continue; }
+                    if keycode == numlock {
+                        self.numlockmask = 1 << i;
+                    }
+                    if keycode == scrolllock {
+                        self.scrolllockmask = 1 << i;
+                    }
+                }
+            }
+            xlib::XFreeModifiermap( modmap );
         }
     }
 
+    // Every combination of the lock modifiers we want bindings to ignore
+    fn lock_masks( &self ) -> [u32; 8] {
+        let caps = xlib::LockMask;
+        let num = self.numlockmask;
+        let scroll = self.scrolllockmask;
+        [ 0, caps, num, scroll, caps|num, caps|scroll, num|scroll, caps|num|scroll ]
+    }
+
+    // Strip lock and pointer button bits from an event state so it can be
+    // compared against the modifiers of a binding.
+    fn clean_mask( &self, mask: u32 ) -> u32 {
+        use x11::xlib::*;
+        mask & !(self.numlockmask | self.scrolllockmask | LockMask)
+             & (ShiftMask|ControlMask|Mod1Mask|Mod2Mask|Mod3Mask|Mod4Mask|Mod5Mask)
+    }
+
     fn get_atom(&self, s: &str) -> u64 {
         unsafe {
             match CString::new(s) {
@@ -144,11 +492,23 @@ impl WindowSystem {
     }
 
     pub fn on_update( &mut self ) -> bool {
+        // A pending chord gives up if no key arrives in time and delayed
+        // focus and raise happen once the pointer has rested long enough
+        let deadline = [ self.keymap_deadline, self.pending_focus.map( |p| p.1 ), self.pending_raise.map( |p| p.1 ) ]
+            .iter().filter_map( |&d| d ).min();
+        if let Some(deadline) = deadline {
+            if !self.wait_for_event( deadline ) {
+                self.run_timers();
+                return false;
+            }
+        }
+
         let mut ev = xlib::XEvent { pad : [0; 24] };
         unsafe {
             xlib::XNextEvent( self.display, &mut ev );
         }
         let event_type = ev.get_type();
+        self.note_time( &ev );
 
         match event_type {
             xlib::MotionNotify => {
@@ -170,29 +530,68 @@ impl WindowSystem {
                 self.on_button_release( &event );
             },
 
-//            xlib::ClientMessage => {
-//                let event = xlib::XClientMessageEvent::from(ev);
-//                self.on_client_message( &event );
-//            },
+            xlib::ClientMessage => {
+                let event = xlib::XClientMessageEvent::from(ev);
+                self.on_client_message( &event );
+            },
 
             xlib::ConfigureNotify => {
                 let event = xlib::XConfigureEvent::from(ev);
 
+                if event.window == self.root {
+                    self.update_monitors();
+                }
+
+                // Only a new size needs a new border pixmap, so a drag that
+                // just moves the window does no drawing. Framed clients take
+                // their geometry from the frame.
+                let window = self.client_of( event.window ).unwrap_or( event.window );
+                let redraw = match self.get_client_mut( window ) {
+                    Some(c) => {
+                        let framed = c.frame.is_some();
+                        if framed && event.window == c.window {
+                            false
+                        } else {
+                            let h = if framed { event.height - config::TITLE_HEIGHT } else { event.height };
+                            c.x = event.x;
+                            c.y = event.y;
+                            c.w = event.width;
+                            c.h = h;
+                            c.bw = event.border_width;
+                            c.drawn.is_none_or( |(w, dh, _)| w != event.width || dh != h )
+                        }
+                    },
+                    None => false,
+                };
+
                 unsafe {
                     xlib::XClearWindow( self.display, self.root );
                 }
 
-                if event.window != self.root {
-                    if event.window == self.focuswin {
-                        self.draw_borders( true, event.window );
-                    }
-                    else {
-                        self.draw_borders( false, event.window );
-                    }
+                if redraw {
+                    let focused = window == self.focuswin;
+                    self.draw_borders( focused, window );
                 }
             }
 
             xlib::DestroyNotify => {
+                let event = xlib::XDestroyWindowEvent::from(ev);
+                self.unmanage( event.window );
+            },
+
+            xlib::UnmapNotify => {
+                // Every unmap reaches both the window and its parent, so only
+                // the copy sent to the window itself counts, along with the
+                // synthetic ones clients send to withdraw
+                let event = xlib::XUnmapEvent::from(ev);
+                if event.event == event.window || event.send_event != 0 {
+                    self.on_unmap( event.window );
+                }
+            },
+
+            xlib::FocusIn => {
+                let event = xlib::XFocusChangeEvent::from(ev);
+                self.on_focus_in( &event );
             },
 
             xlib::EnterNotify => {
@@ -215,6 +614,36 @@ impl WindowSystem {
                 }
             },
 
+            xlib::KeyRelease => {
+                let event = xlib::XKeyEvent::from(ev);
+                self.on_keyrelease( &event );
+            },
+
+            xlib::PropertyNotify => {
+                let event = xlib::XPropertyEvent::from(ev);
+                self.on_property_notify( &event );
+            },
+
+            xlib::Expose => {
+                let event = xlib::XExposeEvent::from(ev);
+                if event.count == 0 {
+                    if let Some(ref switcher) = self.switcher {
+                        if switcher.window() == event.window {
+                            switcher.draw();
+                        }
+                    }
+                    if let Some(window) = self.frame_owner( event.window ) {
+                        let focused = window == self.focuswin;
+                        self.draw_title( window, focused );
+                    }
+                }
+            },
+
+            xlib::MappingNotify => {
+                let mut event = xlib::XMappingEvent::from(ev);
+                self.on_mapping_notify( &mut event );
+            },
+
             _ => {},
         }
         false
@@ -227,142 +656,322 @@ impl WindowSystem {
     }
 
     fn focus( &mut self, window: xlib::Window, time: c_ulong ) {
+        if self.get_client( window ).is_some_and( |c| c.hidden ) {
+            self.deiconify( window );
+        }
+
+        // Getting focus is the attention the window asked for
+        if self.urgent.contains( &window ) {
+            self.clear_urgency_hint( window );
+            self.set_urgent( window, false );
+        }
+
+        // No input windows only ever get raised
+        if self.get_client( window ).is_some_and( |c| !c.can_focus() ) {
+            self.raise_on_focus( window );
+            return;
+        }
+        self.pending_focus = None;
+
         if self.focuswin != window {
-            unsafe{
-                xlib::XSetInputFocus( self.display, window, xlib::RevertToParent, time );
-                if !config::SLOPPYFOCUS {
-                    xlib::XRaiseWindow( self.display, window );
-                }
+            let old = self.focuswin;
+            if self.get_client( old ).is_some() {
+                self.draw_borders( false, old );
             }
+
+            self.give_focus( window, time );
+            self.raise_on_focus( window );
         }
         self.draw_borders( true, window );
         self.focuswin = window;
+
+        // While cycling the history stays put until the cycle is committed
+        if self.cycle.is_none() && self.get_client( window ).is_some() {
+            self.focus_history.retain( |&w| w != window );
+            self.focus_history.insert( 0, window );
+        }
+    }
+
+    // Hand the keyboard to a client the way its ICCCM focus model asks:
+    // passive and locally active clients get XSetInputFocus, locally and
+    // globally active ones get WM_TAKE_FOCUS
+    fn give_focus( &mut self, window: xlib::Window, time: c_ulong ) {
+        let (input, take_focus) = self.get_client( window )
+            .map_or( ( true, false ), |c| ( c.input, c.take_focus ) );
+
+        unsafe {
+            self.focus_serial = xlib::XNextRequest( self.display );
+            if input {
+                xlib::XSetInputFocus( self.display, window, xlib::RevertToParent, time );
+            }
+        }
+        // ICCCM forbids CurrentTime in WM_TAKE_FOCUS, so without an event
+        // time the last one seen from the server stands in
+        if take_focus {
+            let time = if time == xlib::CurrentTime { self.last_time } else { time };
+            self.send_protocol( window, self.get_atom("WM_TAKE_FOCUS"), time );
+        }
+    }
+
+    // Remember the timestamp of events that carry one
+    fn note_time( &mut self, ev: &xlib::XEvent ) {
+        let time = unsafe {
+            match ev.get_type() {
+                xlib::KeyPress | xlib::KeyRelease => ev.key.time,
+                xlib::ButtonPress | xlib::ButtonRelease => ev.button.time,
+                xlib::MotionNotify => ev.motion.time,
+                xlib::EnterNotify | xlib::LeaveNotify => ev.crossing.time,
+                xlib::PropertyNotify => ev.property.time,
+                _ => return,
+            }
+        };
+        if self.last_time == 0 || !time_before( time, self.last_time ) {
+            self.last_time = time;
+        }
     }
 
+    // Paint the concentric rings of FOCUS_BORDERS or UNFOCUSED_BORDERS, the
+    // first one outermost, into the X border of the window.
     fn draw_borders( &mut self, isfocused: bool, window: xlib::Window ) {
-        if self.root as u64 == window as u64 { return; }
+        if self.root == window { return; }
+
+        // In reparenting mode the border goes around the frame
+        let (target, width, height, depth, bw) = match self.get_client( window ) {
+            Some(c) => match c.frame {
+                Some(ref f) => (f.window, c.w, c.h + config::TITLE_HEIGHT, f.depth, c.bw),
+                None => (c.window, c.w, c.h, c.depth, c.bw),
+            },
+            None => return,
+        };
+
+        let urgent = !isfocused && self.urgent.contains( &window );
         let mut borders = config::UNFOCUSED_BORDERS;
-        let mut count = config::NUM_UNFOCUSED_BORDERS;
         let mut size = self.borderinfo.get_unfocus_size();
         if isfocused {
             borders = config::FOCUS_BORDERS;
-            count = config::NUM_FOCUSED_BORDERS;
             size = self.borderinfo.get_focus_size();
+        } else if urgent {
+            borders = config::URGENT_BORDERS;
+            size = self.borderinfo.get_urgent_size();
+        }
+
+        if size != bw {
+            unsafe {
+                xlib::XSetWindowBorderWidth( self.display, target, size as u32 );
+            }
+            if let Some(c) = self.get_client_mut( window ) {
+                c.bw = size;
+            }
+            self.update_frame_extents( window );
         }
+        self.draw_title( window, isfocused );
+        if size <= 0 {
+            return;
+        }
+
+        let outer_w = width + 2 * size;
+        let outer_h = height + 2 * size;
 
         unsafe {
-//            let mut wc = xlib::XWindowChanges {
-//                x: 0,
-//                y: 0,
-//                width: 0,
-//                height: 0,
-//                border_width: size * 2,
-//                sibling: 0,
-//                stack_mode: 0,
-//            };
-//
-//            xlib::XConfigureWindow( self.display, window, xlib::CWBorderWidth as u32, &mut wc );
-
-            let mut gcv = xlib::XGCValues {
-                function: 0,
-                plane_mask: 0,
-                foreground: 0,
-                background: 0,
-                line_width: 0,
-                line_style: 0,
-                cap_style: 0,
-                join_style: 0,
-                fill_style: 0,
-                fill_rule: 0,
-                arc_mode: 0,
-                tile: 0,
-                stipple: 0,
-                ts_x_origin: 0,
-                ts_y_origin: 0,
-                font: 0,
-                subwindow_mode: 0,
-                graphics_exposures: 0,
-                clip_x_origin: 0,
-                clip_y_origin: 0,
-                clip_mask: 0,
-                dash_offset: 0,
-                dashes: 0,
+            let pixmap = xlib::XCreatePixmap( self.display, self.root,
+                                              outer_w as u32, outer_h as u32, depth as u32 );
+            let gc = self.border_gc( pixmap, depth );
+            let pixels = if isfocused {
+                &self.palette.focus
+            } else if urgent {
+                &self.palette.urgent
+            } else {
+                &self.palette.unfocus
             };
-            let mut wa = self.get_empty_wa();
-            xlib::XGetWindowAttributes( self.display, window, &mut wa );
 
-            let cmap = xlib::XDefaultColormap( self.display, 0 );
-            let pixmap = xlib::XCreatePixmap( self.display, self.root,
-                                              (wa.x - size) as u32, (wa.y - size) as u32, wa.depth as u32 );
+            // Each ring is a filled rectangle over the ones outside it, laid
+            // out from the outer corner of the border
+            let mut inset = 0;
+            for (border, &pixel) in borders.iter().zip( pixels.iter() ) {
+                xlib::XSetForeground( self.display, gc, pixel );
+                fill_tiled( self.display, pixmap, gc, size,
+                            ( inset, inset, outer_w - 2 * inset, outer_h - 2 * inset ),
+                            ( outer_w, outer_h ) );
+                inset += border.size;
+            }
+
+            xlib::XSetWindowBorderPixmap( self.display, target, pixmap );
+            xlib::XFreePixmap( self.display, pixmap );
+        }
+
+        if let Some(c) = self.get_client_mut( window ) {
+            c.drawn = Some( (c.w, c.h, isfocused) );
+        }
+        self.flush();
+    }
+
+    fn draw_title( &self, window: xlib::Window, focused: bool ) {
+        if let ( Some(c), Some(style) ) = ( self.get_client( window ), self.frame_style.as_ref() ) {
+            if let Some(ref frame) = c.frame {
+                frame.draw( style, c.w, &c.title, focused );
+            }
+        }
+    }
+
+    // Tell clients how much of their outer size is ours
+    fn update_frame_extents( &self, window: xlib::Window ) {
+        let (l, r, t, b) = match self.get_client( window ) {
+            Some(c) => c.extents(),
+            None => return,
+        };
 
-            for i in 0 .. count {
+        let extents: [c_long; 4] = [ l as c_long, r as c_long, t as c_long, b as c_long ];
+        unsafe {
+            xlib::XChangeProperty( self.display, window, self.get_atom("_NET_FRAME_EXTENTS"), xlib::XA_CARDINAL, 32,
+                xlib::PropModeReplace, extents.as_ptr() as *const c_uchar, 4 );
+        }
+    }
 
-                let new_x = wa.x - size;
-                let new_y = wa.y - size;
-                let new_w = wa.width + ( 2 * size );
-                let new_h = wa.height + ( 2 * size );
+    // One GC per depth is kept around for border drawing, created on first
+    // use from a drawable of that depth
+    unsafe fn border_gc( &mut self, drawable: xlib::Drawable, depth: i32 ) -> xlib::GC {
+        if let Some(&(_, gc)) = self.border_gcs.iter().find( |&&(d, _)| d == depth ) {
+            return gc;
+        }
 
+        let gc = xlib::XCreateGC( self.display, drawable, 0, null_mut() );
+        self.border_gcs.push( (depth, gc) );
+        gc
+    }
 
-                let mut color = xlib::XColor {
-                    pixel: 0,
-                    red: 0,
-                    green: 0,
-                    blue: 0,
-                    flags: 0,
-                    pad: 0,
-                };
+    // Hand the old pixels back with XFreeColors, allocate the configured
+    // colours again and redraw everything that uses them. The snap outline
+    // is built again with the new colour the next time it is shown.
+    fn reload_palette( &mut self ) {
+        unsafe {
+            self.palette.free( self.display );
+            self.palette = Palette::new( self.display );
+        }
 
-                let gc = xlib::XCreateGC( self.display, pixmap, 0, &mut gcv );
-                let color_string = CString::new( borders[i].color).unwrap().into_raw();
+        if let Some(ref mut style) = self.frame_style {
+            style.set_colors( self.palette.title );
+        }
+        self.outline = None;
 
-                xlib::XParseColor( self.display, cmap, color_string, &mut color);
-                xlib::XAllocColor( self.display, cmap, &mut color );
+        let windows: Vec<xlib::Window> = self.clients.iter().map( |c| c.window ).collect();
+        for window in windows {
+            let focused = window == self.focuswin;
+            self.draw_borders( focused, window );
+        }
+    }
 
-                let _ = CString::from_raw(color_string);
+    fn on_enter_notify( &mut self, event: &xlib::XEnterWindowEvent ) {
+        use config::FocusModel;
 
-                xlib::XSetForeground( self.display, gc, color.pixel );
-                xlib::XFillRectangle( self.display, pixmap, gc, new_x, new_y, new_w as u32, new_h as u32 );
-                xlib::XSync( self.display, 0 );
+        if config::FOCUS_MODEL == FocusModel::Click || event.mode != xlib::NotifyNormal {
+            return;
+        }
 
-                xlib::XFreeGC( self.display, gc );
-                size = size - borders[i].size;
+        // The pointer went from a window onto the root itself
+        let target = if event.window == self.root {
+            if event.detail != xlib::NotifyInferior {
+                return;
+            }
+            if config::FOCUS_MODEL == FocusModel::Sloppy {
+                self.pending_focus = None;
+                return;
             }
+            self.root
+        } else {
+            match self.client_of( event.window ) {
+                Some(window) => window,
+                None => return,
+            }
+        };
+
+        if target == self.focuswin {
+            self.pending_focus = None;
+        } else if config::FOCUS_DELAY == 0 {
+            self.focus_pointer( target, event.time );
+        } else {
+            self.pending_focus = Some( ( target, Instant::now() + Duration::from_millis( config::FOCUS_DELAY ) ) );
+        }
+    }
 
-            let mut s_wa = xlib::XSetWindowAttributes {
-                background_pixmap: 0,
-                background_pixel: 0,
-                border_pixmap: pixmap,
-                border_pixel: 0,
-                bit_gravity: 0,
-                win_gravity: 0,
-                backing_store: 0,
-                backing_planes: 0,
-                backing_pixel: 0,
-                save_under: 0,
-                event_mask: 0,
-                do_not_propagate_mask: 0,
-                override_redirect: 0,
-                colormap: 0,
-                cursor: 0,
-            };
+    fn focus_pointer( &mut self, target: xlib::Window, time: c_ulong ) {
+        if target == self.root {
+            self.focus_root( time );
+        } else {
+            self.focus( target, time );
+        }
+        self.flush();
+    }
 
-            xlib::XChangeWindowAttributes( self.display, window,
-                        xlib::CWBorderPixmap, &mut s_wa );
-            xlib::XSync( self.display, 0 );
+    // Fire whatever delayed work is due
+    fn run_timers( &mut self ) {
+        let now = Instant::now();
 
-            xlib::XFreePixmap( self.display, pixmap );
-            xlib::XFreeColormap( self.display, cmap );
-            self.flush();
+        if self.keymap_deadline.is_some_and( |d| d <= now ) {
+            self.leave_keymap( xlib::CurrentTime );
+        }
+        if let Some((target, deadline)) = self.pending_focus {
+            if deadline <= now {
+                self.pending_focus = None;
+                self.focus_pointer( target, xlib::CurrentTime );
+            }
+        }
+        if let Some((window, deadline)) = self.pending_raise {
+            if deadline <= now {
+                self.pending_raise = None;
+                self.raise( window );
+                self.flush();
+            }
         }
     }
 
-    fn on_enter_notify( &mut self, event: &xlib::XEnterWindowEvent ) {
-        if config::SLOPPYFOCUS {
-            self.focus( event.window, event.time );
+    fn raise_on_focus( &mut self, window: xlib::Window ) {
+        if !config::RAISE_ON_FOCUS {
+            return;
+        }
+        if config::RAISE_DELAY == 0 {
+            self.raise( window );
+        } else {
+            self.pending_raise = Some( ( window, Instant::now() + Duration::from_millis( config::RAISE_DELAY ) ) );
+        }
+    }
+
+    // Bring a client to the top. Windows sliding under the pointer because
+    // of it must not steal focus, so the EnterNotify it causes is dropped.
+    fn raise( &mut self, window: xlib::Window ) {
+        if self.get_client( window ).is_none() {
+            return;
+        }
+        unsafe {
+            xlib::XRaiseWindow( self.display, self.outer_window( window ) );
+        }
+        self.drain_enters();
+    }
+
+    fn drain_enters( &self ) {
+        if config::FOCUS_MODEL == config::FocusModel::Click {
+            return;
+        }
+        unsafe {
+            let mut ev = xlib::XEvent { pad : [0; 24] };
+            xlib::XSync( self.display, 0 );
+            while xlib::XCheckMaskEvent( self.display, xlib::EnterWindowMask, &mut ev ) != 0 {}
         }
     }
 
     fn on_map_request( &mut self, event: &mut xlib::XMapRequestEvent ) {
+        // Windows we already manage are simply mapped again, unless they are
+        // leaving IconicState the ICCCM way
+        if let Some(hidden) = self.get_client( event.window ).map( |c| c.hidden ) {
+            if hidden {
+                self.restore_mapped( event.window );
+            } else {
+                unsafe {
+                    xlib::XMapWindow( self.display, event.window );
+                }
+            }
+            return;
+        }
+
         unsafe {
             let mut wa = self.get_empty_wa();
 
@@ -374,117 +983,1489 @@ impl WindowSystem {
                 return;
             }
 
+            // A framed client has no border of its own
+            let bw = self.borderinfo.get_unfocus_size();
             let mut wc = xlib::XWindowChanges {
                 x: wa.x,
                 y: wa.y,
                 width: wa.width,
                 height: wa.height,
-                border_width: 0,
+                border_width: if config::REPARENT { 0 } else { bw },
                 sibling: 0,
                 stack_mode: 0,
             };
 
             xlib::XConfigureWindow( self.display, event.window, xlib::CWBorderWidth as u32, &mut wc );
-            xlib::XSetWindowBorder( self.display, event.window, wc.border_width as u64 );
-            xlib::XMoveResizeWindow( self.display, event.window, wc.x, wc.y, wc.width as u32, wc.height as u32 );
+            if !config::REPARENT {
+                xlib::XMoveResizeWindow( self.display, event.window, wc.x, wc.y, wc.width as u32, wc.height as u32 );
+            }
             xlib::XSelectInput( self.display, event.window,
                         xlib::EnterWindowMask|
                         xlib::FocusChangeMask|
                         xlib::PropertyChangeMask|
                         xlib::StructureNotifyMask );
 
+            let mut client = Client::new( event.window, wa.x, wa.y, wa.width, wa.height );
+            client.depth = wa.depth;
+            client.bw = bw;
+            if config::REPARENT {
+                let frame = Frame::new( self.display, self.root, wa.x, wa.y, wa.width, wa.height, bw );
+                // The save set hands the client back to the root if we die
+                xlib::XAddToSaveSet( self.display, event.window );
+                xlib::XReparentWindow( self.display, event.window, frame.window, 0, config::TITLE_HEIGHT );
+                if wa.map_state != xlib::IsUnmapped {
+                    client.ignore_unmaps += 1;
+                }
+                client.frame = Some( frame );
+            }
+            self.clients.push( client );
+            self.focus_history.push( event.window );
+            self.update_title( event.window );
+            self.update_class( event.window );
+            self.update_size_hints( event.window );
+            self.update_wm_hints( event.window );
+            self.update_focus_model( event.window );
+            self.update_strut( event.window );
+            let pid = self.get_prop32( event.window, self.get_atom("_NET_WM_PID"), xlib::XA_CARDINAL ).unwrap_or( 0 );
+            if let Some(c) = self.get_client_mut( event.window ) {
+                c.pid = pid as u32;
+            }
+            let demands = self.get_atom("_NET_WM_STATE_DEMANDS_ATTENTION");
+            if self.get_net_state( event.window ).contains( &demands ) {
+                self.set_urgent( event.window, true );
+            }
+
+            self.update_frame_extents( event.window );
+            self.set_wm_state( event.window, NORMAL_STATE );
+
+            // A scratchpad that was just started goes straight to its place
+            let pad = self.get_client( event.window )
+                .and_then( |c| config::SCRATCHPADS.iter().find( |pad| pad.matches( &c.class, &c.instance ) ) );
+            let scratchpad = pad.is_some();
+            if let Some(pad) = pad {
+                self.place_scratchpad( pad, event.window );
+            }
+
+            let swallower = if scratchpad { None } else { self.find_swallower( event.window ) };
+            if let Some(terminal) = swallower {
+                self.swallow( terminal, event.window );
+            }
+
             self.draw_borders( false, event.window );
             xlib::XMapWindow( self.display, event.window );
+            let outer = self.outer_window( event.window );
+            if outer != event.window {
+                xlib::XMapWindow( self.display, outer );
+            }
+            let user_time = self.user_time( event.window );
+            if scratchpad || swallower.is_some() || self.may_take_focus( user_time ) {
+                self.focus( event.window, xlib::CurrentTime );
+                self.raise( event.window );
+            } else {
+                self.set_urgent( event.window, true );
+            }
         }
 
     }
 
+    // A minimized client mapped itself again. It is restored like any other
+    // minimized window and gets focus if the focus stealing policy allows.
+    fn restore_mapped( &mut self, window: xlib::Window ) {
+        self.deiconify( window );
+
+        let user_time = self.user_time( window );
+        if self.may_take_focus( user_time ) {
+            self.focus( window, xlib::CurrentTime );
+            self.raise( window );
+        } else {
+            if self.get_client( window ).is_some_and( |c| c.can_focus() ) && !self.focus_history.contains( &window ) {
+                self.focus_history.push( window );
+            }
+            self.set_urgent( window, true );
+        }
+        self.flush();
+    }
+
     fn on_keypress( &mut self, event: &xlib::XKeyEvent ) -> bool {
-        use config::*;
+        self.last_input = event.time;
+        if self.switcher.is_some() {
+            self.on_switcher_key( event );
+            return false;
+        }
 
-        let mut open_term = false;
-        let mut open_run = false;
+        let keysym;
+        let key_info;
 
         unsafe {
-            let key = xlib::XKeysymToString(
-                xlib::XKeycodeToKeysym( self.display, event.keycode as u8, 0 ) );
-            let key = CString::from_raw(key);
-
-            let key_info = KeyCmd::new( key.to_str().unwrap(), event.state );
-
-            // Handle key events
-            match key_info {
-                EXIT_KEY => {
-                    return true;
-                },
-                TERM_KEY => {
-                    open_term = true;
-                },
-                RUN_KEY => {
-                    open_run = true;
-                },
+            // Look the keysym up in the active layout group so bindings follow
+            // setxkbmap and layout switches
+            let group = ((event.state >> 13) & 3) as c_int;
+            keysym = match xlib::XkbKeycodeToKeysym( self.display, event.keycode as u8, group, 0 ) {
+                0 => xlib::XkbKeycodeToKeysym( self.display, event.keycode as u8, 0, 0 ),
+                sym => sym,
+            };
 
-                _ => {},
+            let key = xlib::XKeysymToString( keysym );
+            if key.is_null() {
+                return false;
             }
-        }
-
-        if open_run {
-            spawn(move || {
-                Command::new( RUN ).spawn().unwrap_or_else( |e| {
-                    panic!("Invalid run command {}", e)});
-            });
-        }
+            let key = CStr::from_ptr(key);
 
-        if open_term {
-            spawn(move || {
-                Command::new( TERMINAL ).spawn().unwrap_or_else( |e| {
-                    panic!("Inavlid terminal command {}", e)});
-            });
+            key_info = KeyCmd::new( key.to_str().unwrap_or(""), self.clean_mask( event.state ) );
         }
 
-        false
-    }
-
-    fn on_resize_move( &mut self, event: &xlib::XButtonEvent ) {
-        if event.button == 1 {
-            self.x = event.x_root;
-            self.y = event.y_root;
-        }
-        if event.button == 3 {
-            self.w = event.x_root as u32;
-            self.h = event.y_root as u32;
+        let keymap = self.keymaps.last().map( |&i| &config::KEYMAPS[i] );
+        let binds = match keymap {
+            Some(map) => map.binds,
+            None => config::KEYS,
+        };
+        let action = binds.iter().find( |bind| bind.key == key_info ).map( |bind| bind.action );
+
+        if let Some(map) = keymap {
+            // Modifiers pressed on their own are part of the next chord key
+            if is_modifier_key( keysym ) {
+                return false;
+            }
+            if keysym == keysym::XK_Escape as c_ulong {
+                self.leave_keymap( event.time );
+                return false;
+            }
+            if map.oneshot {
+                self.pop_keymap( event.time );
+            }
         }
 
-        self.button_id = event.button;
-        unsafe {
-            xlib::XGrabPointer( self.display, event.subwindow, 1,
-                (xlib::PointerMotionMask|xlib::ButtonReleaseMask) as u32,
-                xlib::GrabModeAsync, xlib::GrabModeAsync,
-                0, 0, event.time);
+        match action {
+            Some(action) => self.run_action( action, KeyCmd::get_modifier( key_info ), event.time ),
+            None => false,
         }
     }
 
-    fn on_button_press( &mut self, event: &xlib::XButtonEvent ) {
-        let button_info = config::MouseCmd::new( event.button, event.state );
+    // Run a bound action. Returns true when the window manager should exit.
+    fn run_action( &mut self, action: config::Action, mask: u32, time: c_ulong ) -> bool {
+        use config::Action;
 
-        match button_info {
-            config::MOUSE_RESIZE => {
-                if event.subwindow != 0 {
-                    self.focus( event.subwindow, event.time );
-                    self.on_resize_move( &event );
+        let before = self.focuswin;
+        match action {
+            Action::Exit => {
+                return true;
+            },
+            Action::Spawn( cmd ) => {
+                run_command( &[ cmd ] );
+            },
+            Action::EnterKeymap( name ) => {
+                self.enter_keymap( name, time );
+            },
+            Action::LeaveKeymap => {
+                self.leave_keymap( time );
+            },
+            Action::Move( dir ) => {
+                self.move_focused( dir );
+            },
+            Action::Resize( dir ) => {
+                self.resize_focused( dir );
+            },
+            Action::Snap( dir ) => {
+                self.snap_focused( dir );
+            },
+            Action::Center => {
+                self.center_focused();
+            },
+            Action::Focus( dir ) => {
+                if let Some(window) = self.client_in_direction( dir ) {
+                    self.focus( window, time );
+                }
+            },
+            Action::Swap( dir ) => {
+                if let Some(window) = self.client_in_direction( dir ) {
+                    self.swap( window );
+                }
+            },
+            Action::FocusNext => {
+                self.cycle_focus( true, mask, time );
+            },
+            Action::FocusPrev => {
+                self.cycle_focus( false, mask, time );
+            },
+            Action::Switcher => {
+                self.open_switcher( time );
+            },
+            Action::FocusUrgent => {
+                if let Some(&window) = self.urgent.last() {
+                    self.focus( window, time );
+                }
+            },
+            Action::Minimize => {
+                let window = self.focuswin;
+                self.iconify( window, time );
+            },
+            Action::RestoreLast => {
+                if let Some(&window) = self.iconified.last() {
+                    self.focus( window, time );
+                }
+            },
+            Action::PickHidden => {
+                self.pick_hidden();
+            },
+            Action::Scratchpad( name ) => {
+                self.toggle_scratchpad( name, time );
+            },
+            Action::RunOrRaise( class, command ) => {
+                self.run_or_raise( class, command, time );
+            },
+        }
+
+        if self.focuswin != before {
+            self.warp_to_focus();
+        }
+        false
+    }
+
+    fn enter_keymap( &mut self, name: &str, time: c_ulong ) {
+        let index = match config::Keymap::find( name ) {
+            Some(index) => index,
+            None => return,
+        };
+
+        unsafe {
+            if self.keymaps.is_empty() && self.cycle.is_none() &&
+                xlib::XGrabKeyboard( self.display, self.root, 1,
+                    xlib::GrabModeAsync, xlib::GrabModeAsync, time ) != xlib::GrabSuccess {
+                return;
+            }
+        }
+
+        self.keymaps.push( index );
+        self.keymap_deadline = match config::KEYMAPS[index].timeout {
+            0 => None,
+            ms => Some( Instant::now() + Duration::from_millis( ms ) ),
+        };
+        self.announce_mode();
+    }
+
+    fn pop_keymap( &mut self, time: c_ulong ) {
+        self.keymaps.pop();
+        self.keymap_deadline = None;
+        if self.keymaps.is_empty() && self.cycle.is_none() {
+            unsafe {
+                xlib::XUngrabKeyboard( self.display, time );
+            }
+        }
+        self.announce_mode();
+    }
+
+    fn leave_keymap( &mut self, time: c_ulong ) {
+        while !self.keymaps.is_empty() {
+            self.pop_keymap( time );
+        }
+    }
+
+    // Publish the active keymap on the root window as _ALWM_MODE so bars and
+    // scripts can follow mode changes with xprop -spy.
+    fn announce_mode( &self ) {
+        let name = match self.keymaps.last() {
+            Some(&index) => config::KEYMAPS[index].name,
+            None => config::DEFAULT_MODE,
+        };
+
+        let mode = self.get_atom("_ALWM_MODE");
+        let utf8 = self.get_atom("UTF8_STRING");
+        unsafe {
+            xlib::XChangeProperty( self.display, self.root, mode, utf8, 8, xlib::PropModeReplace,
+                name.as_ptr(), name.len() as c_int );
+        }
+        self.flush();
+    }
+
+    // Publish the urgent windows, most recent last, on the root window as
+    // _ALWM_URGENT so bars can highlight them
+    fn announce_urgent( &self ) {
+        let urgent = self.get_atom("_ALWM_URGENT");
+        unsafe {
+            xlib::XChangeProperty( self.display, self.root, urgent, xlib::XA_WINDOW, 32, xlib::PropModeReplace,
+                self.urgent.as_ptr() as *const c_uchar, self.urgent.len() as c_int );
+        }
+        self.flush();
+    }
+
+    // Block until an event arrives or the deadline passes. Returns false on
+    // timeout.
+    fn wait_for_event( &self, deadline: Instant ) -> bool {
+        unsafe {
+            let mut fds = libc::pollfd {
+                fd: xlib::XConnectionNumber( self.display ),
+                events: libc::POLLIN,
+                revents: 0,
+            };
+
+            while xlib::XPending( self.display ) == 0 {
+                let now = Instant::now();
+                if now >= deadline {
+                    return false;
+                }
+
+                let left = deadline - now;
+                let ms = left.as_secs() * 1000 + left.subsec_millis() as u64 + 1;
+                libc::poll( &mut fds, 1, ms as c_int );
+            }
+        }
+        true
+    }
+
+    fn get_client( &self, window: xlib::Window ) -> Option<&Client> {
+        self.clients.iter().find( |c| c.window == window )
+    }
+
+    fn get_client_mut( &mut self, window: xlib::Window ) -> Option<&mut Client> {
+        self.clients.iter_mut().find( |c| c.window == window )
+    }
+
+    // Forget a window that went away
+    fn unmanage( &mut self, window: xlib::Window ) {
+        let swallowed = self.get_client( window )
+            .and_then( |c| c.swallowed.map( |t| ( t, c.x, c.y, c.w, c.h ) ) );
+        let had_focus = self.focuswin == window;
+
+        self.unframe( window );
+        self.clients.retain( |c| c.window != window );
+        self.focus_history.retain( |&w| w != window );
+        self.iconified.retain( |&w| w != window );
+        if let Some(ref mut switcher) = self.switcher {
+            switcher.remove( window );
+        }
+        if self.pending_focus.is_some_and( |p| p.0 == window ) {
+            self.pending_focus = None;
+        }
+        if self.pending_raise.is_some_and( |p| p.0 == window ) {
+            self.pending_raise = None;
+        }
+        if self.urgent.contains( &window ) {
+            self.urgent.retain( |&w| w != window );
+            self.announce_urgent();
+        }
+        if let Some(ref mut cycle) = self.cycle {
+            if let Some(pos) = cycle.order.iter().position( |&w| w == window ) {
+                cycle.order.remove( pos );
+                if pos < cycle.index && cycle.index > 0 {
+                    cycle.index -= 1;
+                }
+            }
+        }
+        for c in self.clients.iter_mut().filter( |c| c.swallowed == Some( window ) ) {
+            c.swallowed = None;
+        }
+
+        // Give a swallowed terminal its place back
+        if let Some((terminal, x, y, w, h)) = swallowed {
+            self.deiconify( terminal );
+            self.move_resize( terminal, x, y, w, h );
+            if had_focus {
+                self.focus( terminal, xlib::CurrentTime );
+            }
+        } else if had_focus {
+            self.focus_fallback( xlib::CurrentTime );
+        }
+    }
+
+    fn on_unmap( &mut self, window: xlib::Window ) {
+        if let Some(c) = self.get_client_mut( window ) {
+            if c.ignore_unmaps > 0 {
+                c.ignore_unmaps -= 1;
+                return;
+            }
+        }
+        if self.get_client( window ).is_some() {
+            self.set_wm_state( window, WITHDRAWN_STATE );
+        }
+        self.unmanage( window );
+    }
+
+    // Move a framed client back onto the root where its frame had it, and
+    // get rid of the frame
+    fn unframe( &mut self, window: xlib::Window ) {
+        let (frame, x, y) = match self.get_client_mut( window ) {
+            Some(c) => {
+                let (l, _, t, _) = c.extents();
+                ( c.frame.take(), c.x + l, c.y + t )
+            },
+            None => return,
+        };
+
+        if let Some(frame) = frame {
+            unsafe {
+                xlib::XReparentWindow( self.display, window, self.root, x, y );
+                xlib::XRemoveFromSaveSet( self.display, window );
+            }
+            drop( frame );
+        }
+    }
+
+    // The client a window belongs to, whether it is the client or its frame
+    fn client_of( &self, window: xlib::Window ) -> Option<xlib::Window> {
+        if self.get_client( window ).is_some() {
+            return Some( window );
+        }
+        self.frame_owner( window )
+    }
+
+    fn frame_owner( &self, frame: xlib::Window ) -> Option<xlib::Window> {
+        self.clients.iter()
+            .find( |c| c.frame.as_ref().is_some_and( |f| f.window == frame ) )
+            .map( |c| c.window )
+    }
+
+    // The top level window of a client, which is what gets moved and stacked
+    fn outer_window( &self, window: xlib::Window ) -> xlib::Window {
+        match self.get_client( window ).and_then( |c| c.frame.as_ref() ) {
+            Some(frame) => frame.window,
+            None => window,
+        }
+    }
+
+    // Ask a client to close through WM_DELETE_WINDOW, or kill it if it does
+    // not take part
+    fn close_window( &self, window: xlib::Window, time: c_ulong ) {
+        if !self.send_protocol( window, self.get_atom("WM_DELETE_WINDOW"), time ) {
+            unsafe {
+                xlib::XKillClient( self.display, window );
+            }
+        }
+        self.flush();
+    }
+
+    fn supports_protocol( &self, window: xlib::Window, protocol: xlib::Atom ) -> bool {
+        unsafe {
+            let mut protocols: *mut xlib::Atom = null_mut();
+            let mut count = 0;
+            if xlib::XGetWMProtocols( self.display, window, &mut protocols, &mut count ) == 0 || protocols.is_null() {
+                return false;
+            }
+            let found = ::std::slice::from_raw_parts( protocols, count as usize ).contains( &protocol );
+            xlib::XFree( protocols as *mut _ );
+            found
+        }
+    }
+
+    // Send a WM_PROTOCOLS message if the client lists the protocol
+    fn send_protocol( &self, window: xlib::Window, protocol: xlib::Atom, time: c_ulong ) -> bool {
+        if !self.supports_protocol( window, protocol ) {
+            return false;
+        }
+
+        let mut data = xlib::ClientMessageData::new();
+        data.set_long( 0, protocol as c_long );
+        data.set_long( 1, time as c_long );
+        let message = xlib::XClientMessageEvent {
+            type_: xlib::ClientMessage,
+            serial: 0,
+            send_event: 1,
+            display: self.display,
+            window,
+            message_type: self.get_atom("WM_PROTOCOLS"),
+            format: 32,
+            data,
+        };
+
+        unsafe {
+            let mut event = xlib::XEvent::from( message );
+            xlib::XSendEvent( self.display, window, 0, xlib::NoEventMask, &mut event );
+        }
+        true
+    }
+
+    // Fill the monitor with a window, or put it back where it was
+    fn toggle_maximize( &mut self, window: xlib::Window ) {
+        let (x, y, w, h, (l, r, t, b), saved) = match self.get_client( window ) {
+            Some(c) => ( c.x, c.y, c.w, c.h, c.extents(), c.saved_geom ),
+            None => return,
+        };
+
+        match saved {
+            Some((sx, sy, sw, sh)) => {
+                if let Some(c) = self.get_client_mut( window ) {
+                    c.saved_geom = None;
+                }
+                self.move_resize( window, sx, sy, sw, sh );
+            },
+            None => {
+                let (mx, my, mw, mh) = self.monitor_at( x + w / 2, y + h / 2 );
+                if let Some(c) = self.get_client_mut( window ) {
+                    c.saved_geom = Some( (x, y, w, h) );
+                }
+                self.move_resize( window, mx, my, mw - l - r, mh - t - b );
+            },
+        }
+        self.flush();
+    }
+
+    // Minimize a window. It stays managed and comes back through restore,
+    // the hidden window picker, the switcher or anything that focuses it.
+    fn iconify( &mut self, window: xlib::Window, time: c_ulong ) {
+        if !self.hide( window ) {
+            return;
+        }
+
+        self.iconified.retain( |&w| w != window );
+        self.iconified.push( window );
+        self.set_wm_state( window, ICONIC_STATE );
+        let hidden = self.get_atom("_NET_WM_STATE_HIDDEN");
+        let mut state = self.get_net_state( window );
+        if !state.contains( &hidden ) {
+            state.push( hidden );
+            self.set_net_state( window, &state );
+        }
+
+        if self.focuswin == window {
+            self.focus_fallback( time );
+        }
+        self.flush();
+    }
+
+    // Unmap a client while keeping it managed. Returns false if it was not
+    // showing.
+    fn hide( &mut self, window: xlib::Window ) -> bool {
+        match self.get_client_mut( window ) {
+            Some(ref mut c) if !c.hidden => {
+                c.hidden = true;
+                c.ignore_unmaps += 1;
+            },
+            _ => return false,
+        }
+
+        let outer = self.outer_window( window );
+        unsafe {
+            xlib::XUnmapWindow( self.display, window );
+            if outer != window {
+                xlib::XUnmapWindow( self.display, outer );
+            }
+        }
+        // Like a workspace switch, whatever the pointer ends up over did not
+        // ask for focus
+        self.drain_enters();
+
+        self.focus_history.retain( |&w| w != window );
+        true
+    }
+
+    // The terminal a new window was started from, found by walking up the
+    // process tree from its _NET_WM_PID
+    fn find_swallower( &self, window: xlib::Window ) -> Option<xlib::Window> {
+        let c = self.get_client( window )?;
+        if !config::SWALLOW || c.pid == 0 || c.class_in( config::NO_SWALLOW ) || c.class_in( config::SWALLOW_TERMINALS ) {
+            return None;
+        }
+
+        let mut pid = c.pid;
+        while pid > 1 {
+            pid = parent_pid( pid )?;
+            let terminal = self.clients.iter().find( |t| {
+                t.pid == pid && !t.hidden && t.window != window && t.class_in( config::SWALLOW_TERMINALS )
+            } );
+            if let Some(t) = terminal {
+                return Some( t.window );
+            }
+        }
+        None
+    }
+
+    // Put a new window in place of its terminal and hide the terminal
+    fn swallow( &mut self, terminal: xlib::Window, window: xlib::Window ) {
+        let (x, y, w, h) = match self.get_client( terminal ) {
+            Some(t) => ( t.x, t.y, t.w, t.h ),
+            None => return,
+        };
+
+        if !self.hide( terminal ) {
+            return;
+        }
+        self.set_wm_state( terminal, ICONIC_STATE );
+        if let Some(c) = self.get_client_mut( window ) {
+            c.swallowed = Some( terminal );
+        }
+        self.move_resize( window, x, y, w, h );
+    }
+
+    // A client moved focus itself. Events carry the serial of the last
+    // request of ours the server had handled, so anything older than our
+    // last XSetInputFocus is stale. One with exactly focus_serial may still
+    // come from a client that took focus right after us; our own is told
+    // apart by landing on focuswin. The rest is held to the focus stealing
+    // policy.
+    fn on_focus_in( &mut self, event: &xlib::XFocusChangeEvent ) {
+        if event.mode == xlib::NotifyGrab || event.mode == xlib::NotifyUngrab || event.serial < self.focus_serial {
+            return;
+        }
+
+        let window = match self.client_of( event.window ) {
+            Some(window) => window,
+            None => return,
+        };
+        if window == self.focuswin {
+            return;
+        }
+
+        let user_time = self.user_time( window );
+        if self.may_take_focus( user_time ) {
+            self.focus( window, xlib::CurrentTime );
+            return;
+        }
+
+        let current = self.focuswin;
+        self.set_urgent( window, true );
+        if self.get_client( current ).is_some() {
+            self.give_focus( current, xlib::CurrentTime );
+        } else {
+            self.focus_root( xlib::CurrentTime );
+        }
+        self.flush();
+    }
+
+    // Whether a window may take focus on its own, given the user time it
+    // claims or None when it has none
+    fn may_take_focus( &self, time: Option<c_ulong> ) -> bool {
+        use config::FocusStealing;
+
+        match config::FOCUS_STEALING {
+            FocusStealing::Allow => true,
+            FocusStealing::Deny => false,
+            FocusStealing::Smart => match time {
+                // A user time of 0 asks not to be focused
+                Some(0) => false,
+                Some(time) => self.last_input == 0 || !time_before( time, self.last_input ),
+                None => true,
+            },
+        }
+    }
+
+    // _NET_WM_USER_TIME, which may live on a separate window named by
+    // _NET_WM_USER_TIME_WINDOW
+    fn user_time( &self, window: xlib::Window ) -> Option<c_ulong> {
+        let source = self.get_prop32( window, self.get_atom("_NET_WM_USER_TIME_WINDOW"), xlib::XA_WINDOW )
+            .unwrap_or( window );
+        self.get_prop32( source, self.get_atom("_NET_WM_USER_TIME"), xlib::XA_CARDINAL )
+    }
+
+    fn get_prop32( &self, window: xlib::Window, atom: xlib::Atom, kind: xlib::Atom ) -> Option<c_ulong> {
+        match self.get_prop32_list( window, atom, kind, 1 )[..] {
+            [ value ] => Some( value ),
+            _ => None,
+        }
+    }
+
+    // Up to max values of a format 32 property
+    fn get_prop32_list( &self, window: xlib::Window, atom: xlib::Atom, kind: xlib::Atom, max: usize ) -> Vec<c_ulong> {
+        let mut values = Vec::new();
+        unsafe {
+            let mut actual_type = 0;
+            let mut format = 0;
+            let mut nitems = 0;
+            let mut after = 0;
+            let mut data: *mut c_uchar = null_mut();
+
+            let status = xlib::XGetWindowProperty( self.display, window, atom, 0, max as c_long, 0, kind,
+                &mut actual_type, &mut format, &mut nitems, &mut after, &mut data );
+            if status != xlib::Success as c_int || data.is_null() {
+                return values;
+            }
+            if format == 32 {
+                values.extend_from_slice( ::std::slice::from_raw_parts( data as *const c_ulong, nitems as usize ) );
+            }
+            xlib::XFree( data as *mut _ );
+        }
+        values
+    }
+
+    // Read the space a dock reserves. _NET_WM_STRUT_PARTIAL wins, the older
+    // _NET_WM_STRUT covers the whole length of each edge.
+    fn update_strut( &mut self, window: xlib::Window ) {
+        let partial = self.get_prop32_list( window, self.get_atom("_NET_WM_STRUT_PARTIAL"), xlib::XA_CARDINAL, 12 );
+        let strut = if partial.len() == 12 {
+            let mut strut = [0; 12];
+            for (value, &raw) in strut.iter_mut().zip( partial.iter() ) {
+                *value = raw as i32;
+            }
+            Some( strut )
+        } else {
+            match self.get_prop32_list( window, self.get_atom("_NET_WM_STRUT"), xlib::XA_CARDINAL, 4 )[..] {
+                [ left, right, top, bottom ] => Some( [ left as i32, right as i32, top as i32, bottom as i32,
+                                                        0, i32::MAX, 0, i32::MAX, 0, i32::MAX, 0, i32::MAX ] ),
+                _ => None,
+            }
+        };
+
+        if let Some(c) = self.get_client_mut( window ) {
+            c.strut = strut;
+        }
+    }
+
+    // The part of a monitor that docks leave free
+    fn work_area( &self, monitor: (i32, i32, i32, i32) ) -> (i32, i32, i32, i32) {
+        let screen = unsafe {
+            let screen = xlib::XDefaultScreen( self.display );
+            ( xlib::XDisplayWidth( self.display, screen ), xlib::XDisplayHeight( self.display, screen ) )
+        };
+        let struts: Vec<[i32; 12]> = self.clients.iter()
+            .filter( |c| !c.hidden )
+            .filter_map( |c| c.strut )
+            .collect();
+        free_area( monitor, screen, &struts )
+    }
+
+    fn deiconify( &mut self, window: xlib::Window ) {
+        match self.get_client_mut( window ) {
+            Some(ref mut c) if c.hidden => c.hidden = false,
+            _ => return,
+        }
+
+        self.iconified.retain( |&w| w != window );
+        self.set_wm_state( window, NORMAL_STATE );
+        let hidden = self.get_atom("_NET_WM_STATE_HIDDEN");
+        let mut state = self.get_net_state( window );
+        state.retain( |&a| a != hidden );
+        self.set_net_state( window, &state );
+
+        let outer = self.outer_window( window );
+        unsafe {
+            xlib::XMapWindow( self.display, window );
+            if outer != window {
+                xlib::XMapRaised( self.display, outer );
+            }
+        }
+        self.drain_enters();
+    }
+
+    // Offer the minimized windows, most recent first, in the MENU program.
+    // The menu runs on its own thread and the pick comes back as an
+    // _ALWM_RESTORE message to the root, so alwm keeps handling events.
+    fn pick_hidden( &self ) {
+        let entries: Vec<(xlib::Window, String)> = self.iconified.iter().rev()
+            .filter_map( |&w| self.get_client( w ) )
+            .enumerate()
+            .map( |(i, c)| ( c.window, format!( "{} {}  [{}]", i + 1, c.title, c.class ) ) )
+            .collect();
+        if entries.is_empty() || config::MENU.is_empty() {
+            return;
+        }
+
+        spawn(move || {
+            let mut child = match Command::new( config::MENU[0] ).args( &config::MENU[1..] )
+                    .stdin( Stdio::piped() ).stdout( Stdio::piped() ).spawn() {
+                Ok(child) => child,
+                Err(e) => {
+                    eprintln!( "alwm: cannot run {}: {}", config::MENU[0], e );
+                    return;
+                },
+            };
+
+            if let Some(mut stdin) = child.stdin.take() {
+                for (_, line) in entries.iter() {
+                    if writeln!( stdin, "{}", line ).is_err() {
+                        break;
+                    }
+                }
+            }
+
+            let picked = match child.wait_with_output() {
+                Ok(output) => String::from_utf8_lossy( &output.stdout ).trim_end().to_string(),
+                Err(_) => return,
+            };
+            if let Some(&(window, _)) = entries.iter().find( |(_, line)| *line == picked ) {
+                send_restore( window );
+            }
+        });
+    }
+
+    // Take focus away from every client. The keyboard is parked on our own
+    // window so keys never go to whatever happens to be under the pointer.
+    fn focus_root( &mut self, time: c_ulong ) {
+        let old = self.focuswin;
+        if self.get_client( old ).is_some() {
+            self.draw_borders( false, old );
+        }
+        self.focuswin = self.root;
+        self.pending_focus = None;
+        self.pending_raise = None;
+        unsafe {
+            self.focus_serial = xlib::XNextRequest( self.display );
+            xlib::XSetInputFocus( self.display, self.support, xlib::RevertToPointerRoot, time );
+        }
+    }
+
+    // Focus the most recently used window after the focused one went away
+    fn focus_fallback( &mut self, time: c_ulong ) {
+        match self.focus_history.first() {
+            Some(&window) => self.focus( window, time ),
+            None => self.focus_root( time ),
+        }
+    }
+
+    // The closest client to the focused one in the given direction. Clients
+    // are compared in root coordinates so this crosses monitors too.
+    fn client_in_direction( &self, dir: config::Direction ) -> Option<xlib::Window> {
+        let current = self.get_client( self.focuswin )?;
+
+        self.clients.iter()
+            .filter( |c| c.window != current.window && !c.hidden && c.can_focus() )
+            .filter_map( |c| current.distance_to( c, dir ).map( |d| (d, c.window) ) )
+            .min_by_key( |&(d, _)| d )
+            .map( |(_, window)| window )
+    }
+
+    // Trade places with another client, keeping focus where it is
+    fn swap( &mut self, other: xlib::Window ) {
+        let (a, b) = match ( self.get_client( self.focuswin ), self.get_client( other ) ) {
+            ( Some(a), Some(b) ) => ( (a.window, a.x, a.y, a.w, a.h), (b.window, b.x, b.y, b.w, b.h) ),
+            _ => return,
+        };
+
+        self.configure_client( a.0, b.1, b.2, b.3, b.4 );
+        self.configure_client( b.0, a.1, a.2, a.3, a.4 );
+        self.flush();
+    }
+
+    // Show the switcher with clients in most recently used order
+    fn open_switcher( &mut self, time: c_ulong ) {
+        if self.switcher.is_some() || self.clients.is_empty() {
+            return;
+        }
+
+        // Leave any mode first so the switcher owns the keyboard
+        self.leave_keymap( time );
+        self.end_cycle( time );
+
+        let mut order = self.focus_history.clone();
+        for c in self.clients.iter() {
+            if !order.contains( &c.window ) {
+                order.push( c.window );
+            }
+        }
+
+        let entries = order.iter()
+            .filter_map( |&w| self.get_client( w ) )
+            .map( |c| Entry { window: c.window, title: c.title.clone(), class: c.class.clone() } )
+            .collect();
+
+        unsafe {
+            if xlib::XGrabKeyboard( self.display, self.root, 1,
+                    xlib::GrabModeAsync, xlib::GrabModeAsync, time ) != xlib::GrabSuccess {
+                return;
+            }
+            let (px, py, _) = self.query_pointer();
+            let monitor = self.monitor_at( px, py );
+            let switcher = Switcher::new( self.display, self.root, self.text.clone(), entries,
+                                          self.palette.switcher, monitor );
+            switcher.draw();
+            self.switcher = Some( switcher );
+        }
+    }
+
+    fn on_switcher_key( &mut self, event: &xlib::XKeyEvent ) {
+        let mut event = *event;
+        let result = match self.switcher {
+            Some(ref mut switcher) => switcher.on_key( &mut event ),
+            None => return,
+        };
+
+        let picked = match result {
+            SwitcherEvent::Continue => return,
+            SwitcherEvent::Cancel => false,
+            // The entries are a snapshot, so make sure the window is still ours
+            SwitcherEvent::Select( window ) if self.get_client( window ).is_some() => {
+                self.focus( window, event.time );
+                true
+            },
+            SwitcherEvent::Select(_) => false,
+        };
+
+        self.switcher = None;
+        unsafe {
+            xlib::XUngrabKeyboard( self.display, event.time );
+        }
+        if picked {
+            self.warp_to_focus();
+        }
+        self.flush();
+    }
+
+    fn on_property_notify( &mut self, event: &xlib::XPropertyEvent ) {
+        if event.state == xlib::PropertyDelete || self.get_client( event.window ).is_none() {
+            return;
+        }
+
+        if event.atom == xlib::XA_WM_NAME || event.atom == self.get_atom("_NET_WM_NAME") {
+            self.update_title( event.window );
+        }
+        if event.atom == xlib::XA_WM_CLASS {
+            self.update_class( event.window );
+        }
+        if event.atom == xlib::XA_WM_NORMAL_HINTS {
+            self.update_size_hints( event.window );
+        }
+        if event.atom == xlib::XA_WM_HINTS {
+            self.update_wm_hints( event.window );
+            self.update_focus_model( event.window );
+        }
+        if event.atom == self.get_atom("WM_PROTOCOLS") {
+            self.update_focus_model( event.window );
+        }
+        if event.atom == self.get_atom("_NET_WM_STRUT_PARTIAL") || event.atom == self.get_atom("_NET_WM_STRUT") {
+            self.update_strut( event.window );
+        }
+    }
+
+    fn on_client_message( &mut self, event: &xlib::XClientMessageEvent ) {
+        // Sent to the root by the hidden window picker
+        if event.window == self.root && event.message_type == self.get_atom("_ALWM_RESTORE") {
+            let window = event.data.get_long( 0 ) as xlib::Window;
+            if self.iconified.contains( &window ) {
+                self.focus( window, xlib::CurrentTime );
+                self.warp_to_focus();
+            }
+            return;
+        }
+
+        if self.get_client( event.window ).is_none() {
+            return;
+        }
+
+        if event.message_type == self.get_atom("WM_CHANGE_STATE") && event.data.get_long( 0 ) == ICONIC_STATE as c_long {
+            self.iconify( event.window, xlib::CurrentTime );
+        }
+
+        if event.message_type == self.get_atom("_NET_ACTIVE_WINDOW") {
+            // data is [ source, timestamp, requestor's active window ]. Pagers
+            // and taskbars act for the user, applications have to prove it,
+            // and old clients that give neither source nor time are let be.
+            let source = event.data.get_long( 0 );
+            let time = event.data.get_long( 1 ) as c_ulong;
+            let allowed = match source {
+                2 => true,
+                0 if time == 0 => self.may_take_focus( None ),
+                _ => self.may_take_focus( Some( time ) ),
+            };
+
+            if allowed {
+                self.focus( event.window, xlib::CurrentTime );
+                self.raise( event.window );
+                // Pagers and taskbars speak for the user
+                if source == 2 {
+                    self.warp_to_focus();
+                }
+                self.flush();
+            } else {
+                self.set_urgent( event.window, true );
+            }
+        }
+
+        if event.message_type == self.get_atom("_NET_WM_STATE") {
+            // data is [ action, first property, second property, source ]
+            let demands = self.get_atom("_NET_WM_STATE_DEMANDS_ATTENTION");
+            let data = event.data.as_longs();
+            if data[1] as xlib::Atom == demands || data[2] as xlib::Atom == demands {
+                let urgent = match data[0] {
+                    0 => false,
+                    1 => true,
+                    _ => !self.urgent.contains( &event.window ),
+                };
+                self.set_urgent( event.window, urgent );
+            }
+        }
+    }
+
+    // Mark or clear a window as wanting attention. The focused window never
+    // counts as urgent.
+    fn set_urgent( &mut self, window: xlib::Window, urgent: bool ) {
+        let urgent = urgent && window != self.focuswin;
+        if urgent == self.urgent.contains( &window ) {
+            return;
+        }
+
+        self.urgent.retain( |&w| w != window );
+        if urgent {
+            self.urgent.push( window );
+        }
+
+        let demands = self.get_atom("_NET_WM_STATE_DEMANDS_ATTENTION");
+        let mut state = self.get_net_state( window );
+        state.retain( |&a| a != demands );
+        if urgent {
+            state.push( demands );
+        }
+        self.set_net_state( window, &state );
+
+        self.draw_borders( false, window );
+        self.announce_urgent();
+    }
+
+    fn update_wm_hints( &mut self, window: xlib::Window ) {
+        let urgent = unsafe {
+            let hints = xlib::XGetWMHints( self.display, window );
+            if hints.is_null() {
+                return;
+            }
+            let urgent = (*hints).flags & xlib::XUrgencyHint != 0;
+            xlib::XFree( hints as *mut _ );
+            urgent
+        };
+
+        // Clients clear the hint themselves, but _NET_WM_STATE may still ask
+        if urgent || !self.get_net_state( window ).contains( &self.get_atom("_NET_WM_STATE_DEMANDS_ATTENTION") ) {
+            self.set_urgent( window, urgent );
+        }
+    }
+
+    // Work out which ICCCM focus model the client follows. Windows that take
+    // no input are kept out of the focus history so nothing lands on them.
+    // Hidden windows are out of the history already and stay out, so a
+    // property change never brings back a minimized window or a swallowed
+    // terminal.
+    fn update_focus_model( &mut self, window: xlib::Window ) {
+        let input = unsafe {
+            let hints = xlib::XGetWMHints( self.display, window );
+            if hints.is_null() {
+                true
+            } else {
+                let input = (*hints).flags & xlib::InputHint == 0 || (*hints).input != 0;
+                xlib::XFree( hints as *mut _ );
+                input
+            }
+        };
+        let take_focus = self.supports_protocol( window, self.get_atom("WM_TAKE_FOCUS") );
+
+        let (can_focus, hidden) = match self.get_client_mut( window ) {
+            Some(c) => {
+                c.input = input;
+                c.take_focus = take_focus;
+                ( c.can_focus(), c.hidden )
+            },
+            None => return,
+        };
+
+        if hidden {
+            return;
+        }
+        if !can_focus {
+            self.focus_history.retain( |&w| w != window );
+        } else if !self.focus_history.contains( &window ) {
+            self.focus_history.push( window );
+        }
+    }
+
+    // Drop the urgency flag from WM_HINTS like the ICCCM asks once the user
+    // has seen the window
+    fn clear_urgency_hint( &self, window: xlib::Window ) {
+        unsafe {
+            let hints = xlib::XGetWMHints( self.display, window );
+            if hints.is_null() {
+                return;
+            }
+            if (*hints).flags & xlib::XUrgencyHint != 0 {
+                (*hints).flags &= !xlib::XUrgencyHint;
+                xlib::XSetWMHints( self.display, window, hints );
+            }
+            xlib::XFree( hints as *mut _ );
+        }
+    }
+
+    fn set_wm_state( &self, window: xlib::Window, state: c_int ) {
+        let data: [c_long; 2] = [ state as c_long, 0 ];
+        let atom = self.get_atom("WM_STATE");
+        unsafe {
+            xlib::XChangeProperty( self.display, window, atom, atom, 32,
+                xlib::PropModeReplace, data.as_ptr() as *const c_uchar, 2 );
+        }
+    }
+
+    fn get_net_state( &self, window: xlib::Window ) -> Vec<xlib::Atom> {
+        let mut state = Vec::new();
+        unsafe {
+            let mut actual_type = 0;
+            let mut format = 0;
+            let mut nitems = 0;
+            let mut after = 0;
+            let mut data: *mut c_uchar = null_mut();
+
+            let status = xlib::XGetWindowProperty( self.display, window, self.get_atom("_NET_WM_STATE"),
+                0, 64, 0, xlib::XA_ATOM, &mut actual_type, &mut format, &mut nitems, &mut after, &mut data );
+            if status == xlib::Success as c_int && !data.is_null() {
+                if format == 32 {
+                    let atoms = ::std::slice::from_raw_parts( data as *const xlib::Atom, nitems as usize );
+                    state.extend_from_slice( atoms );
+                }
+                xlib::XFree( data as *mut _ );
+            }
+        }
+        state
+    }
+
+    fn set_net_state( &self, window: xlib::Window, state: &[xlib::Atom] ) {
+        unsafe {
+            xlib::XChangeProperty( self.display, window, self.get_atom("_NET_WM_STATE"), xlib::XA_ATOM, 32,
+                xlib::PropModeReplace, state.as_ptr() as *const c_uchar, state.len() as c_int );
+        }
+    }
+
+    // Read a text property as UTF-8, replacing anything that does not decode
+    fn get_text_prop( &self, window: xlib::Window, atom: xlib::Atom ) -> Option<String> {
+        unsafe {
+            let mut prop = xlib::XTextProperty {
+                value: null_mut(),
+                encoding: 0,
+                format: 0,
+                nitems: 0,
+            };
+
+            if xlib::XGetTextProperty( self.display, window, &mut prop, atom ) == 0 || prop.value.is_null() {
+                return None;
+            }
+
+            let bytes = ::std::slice::from_raw_parts( prop.value, prop.nitems as usize );
+            let text = String::from_utf8_lossy( bytes ).into_owned();
+            xlib::XFree( prop.value as *mut _ );
+
+            if text.is_empty() { None } else { Some( text ) }
+        }
+    }
+
+    fn update_title( &mut self, window: xlib::Window ) {
+        let title = self.get_text_prop( window, self.get_atom("_NET_WM_NAME") )
+            .or_else( || self.get_text_prop( window, xlib::XA_WM_NAME ) )
+            .unwrap_or_default();
+
+        if let Some(c) = self.get_client_mut( window ) {
+            c.title = title;
+        }
+        let focused = window == self.focuswin;
+        self.draw_title( window, focused );
+    }
+
+    fn update_class( &mut self, window: xlib::Window ) {
+        let mut instance = String::new();
+        let mut class = String::new();
+
+        unsafe {
+            let mut hint = xlib::XClassHint {
+                res_name: null_mut(),
+                res_class: null_mut(),
+            };
+
+            if xlib::XGetClassHint( self.display, window, &mut hint ) != 0 {
+                if !hint.res_name.is_null() {
+                    instance = CStr::from_ptr( hint.res_name ).to_string_lossy().into_owned();
+                    xlib::XFree( hint.res_name as *mut _ );
+                }
+                if !hint.res_class.is_null() {
+                    class = CStr::from_ptr( hint.res_class ).to_string_lossy().into_owned();
+                    xlib::XFree( hint.res_class as *mut _ );
+                }
+            }
+        }
+
+        if let Some(c) = self.get_client_mut( window ) {
+            c.instance = instance;
+            c.class = class;
+        }
+    }
+
+    fn update_size_hints( &mut self, window: xlib::Window ) {
+        let mut hints: xlib::XSizeHints = unsafe { ::std::mem::zeroed() };
+        let mut supplied = 0;
+        let ok = unsafe {
+            xlib::XGetWMNormalHints( self.display, window, &mut hints, &mut supplied ) != 0
+        };
+
+        if let Some(c) = self.get_client_mut( window ) {
+            c.set_size_hints( if ok { Some( &hints ) } else { None } );
+        }
+    }
+
+    // Releasing the modifier that started a focus cycle commits it
+    fn on_keyrelease( &mut self, event: &xlib::XKeyEvent ) {
+        let mask = match self.cycle {
+            Some(ref cycle) => cycle.mask,
+            None => return,
+        };
+
+        let (_, _, state) = self.query_pointer();
+        if self.clean_mask( state ) & mask == 0 {
+            self.end_cycle( event.time );
+        }
+    }
+
+    // Pointer position on the root and the modifier and button state
+    fn query_pointer( &self ) -> (i32, i32, u32) {
+        let mut root = 0;
+        let mut child = 0;
+        let (mut rx, mut ry, mut wx, mut wy) = (0, 0, 0, 0);
+        let mut state = 0;
+        unsafe {
+            xlib::XQueryPointer( self.display, self.root, &mut root, &mut child,
+                &mut rx, &mut ry, &mut wx, &mut wy, &mut state );
+        }
+        ( rx, ry, state )
+    }
+
+    // Bring the pointer along after focus moved from the keyboard, so it does
+    // not sit over another window that focus follows the mouse back to
+    fn warp_to_focus( &mut self ) {
+        if !config::WARP_POINTER {
+            return;
+        }
+        let (x, y, w, h) = match self.get_client( self.focuswin ) {
+            Some(c) => {
+                let (l, r, t, b) = c.extents();
+                ( c.x, c.y, c.w + l + r, c.h + t + b )
+            },
+            None => return,
+        };
+
+        let (px, py, _) = self.query_pointer();
+        if px >= x && px < x + w && py >= y && py < y + h {
+            return;
+        }
+
+        unsafe {
+            xlib::XWarpPointer( self.display, 0, self.root, 0, 0, 0, 0, x + w / 2, y + h / 2 );
+        }
+        self.drain_enters();
+    }
+
+    // Start a scratchpad if it has no window yet. Otherwise hide it when it
+    // has focus, or bring it to the monitor under the pointer and focus it.
+    fn toggle_scratchpad( &mut self, name: &str, time: c_ulong ) {
+        let pad = match config::Scratchpad::find( name ) {
+            Some(pad) => pad,
+            None => return,
+        };
+
+        let window = self.clients.iter()
+            .find( |c| pad.matches( &c.class, &c.instance ) )
+            .map( |c| c.window );
+
+        match window {
+            None => {
+                run_command( pad.command );
+            },
+            // Put away rather than minimized, so it stays out of the restore
+            // list and pagers do not show it as minimized
+            Some(window) if window == self.focuswin => {
+                if self.hide( window ) {
+                    self.set_wm_state( window, ICONIC_STATE );
+                    self.focus_fallback( time );
+                    self.flush();
+                }
+            },
+            Some(window) => {
+                self.place_scratchpad( pad, window );
+                self.focus( window, time );
+                self.raise( window );
+                self.flush();
+            },
+        }
+    }
+
+    fn run_or_raise( &mut self, class: &str, command: &'static [&'static str], time: c_ulong ) {
+        let matches: Vec<xlib::Window> = self.clients.iter()
+            .filter( |c| c.class_in( &[ class ] ) )
+            .map( |c| c.window )
+            .collect();
+
+        if matches.is_empty() {
+            run_command( command );
+            return;
+        }
+
+        // From a match go on to the next one, otherwise start with the one
+        // used most recently
+        let window = match matches.iter().position( |&w| w == self.focuswin ) {
+            Some(pos) => matches[( pos + 1 ) % matches.len()],
+            None => self.focus_history.iter().cloned()
+                .find( |w| matches.contains( w ) )
+                .unwrap_or( matches[0] ),
+        };
+
+        self.focus( window, time );
+        self.raise( window );
+        self.flush();
+    }
+
+    fn place_scratchpad( &mut self, pad: &config::Scratchpad, window: xlib::Window ) {
+        let (px, py, _) = self.query_pointer();
+        let (mx, my, mw, mh) = self.monitor_at( px, py );
+        let (l, r, t, b) = self.extents( window );
+        let (gx, gy, gw, gh) = pad.geometry;
+
+        self.move_resize( window,
+            mx + mw * gx / 100, my + mh * gy / 100,
+            mw * gw / 100 - l - r, mh * gh / 100 - t - b );
+    }
+
+    // Step through the focus history in most recently used order. The first
+    // step grabs the keyboard so we see the modifier being released.
+    fn cycle_focus( &mut self, forward: bool, mask: u32, time: c_ulong ) {
+        if self.cycle.is_none() {
+            if self.focus_history.len() < 2 {
+                return;
+            }
+
+            unsafe {
+                if self.keymaps.is_empty() &&
+                    xlib::XGrabKeyboard( self.display, self.root, 1,
+                        xlib::GrabModeAsync, xlib::GrabModeAsync, time ) != xlib::GrabSuccess {
+                    return;
+                }
+            }
+
+            let mut order = self.focus_history.clone();
+            // Start from the focused window even if it was never recorded
+            if let Some(pos) = order.iter().position( |&w| w == self.focuswin ) {
+                let current = order.remove( pos );
+                order.insert( 0, current );
+            }
+
+            self.cycle = Some( FocusCycle {
+                order,
+                index: 0,
+                mask: mask & !xlib::ShiftMask,
+            } );
+        }
+
+        let window = match self.cycle {
+            Some(ref mut cycle) => {
+                let len = cycle.order.len();
+                if len == 0 {
+                    return;
+                }
+                cycle.index = if forward {
+                    ( cycle.index + 1 ) % len
+                } else {
+                    ( cycle.index + len - 1 ) % len
+                };
+                cycle.order[cycle.index]
+            },
+            None => return,
+        };
+
+        self.focus( window, time );
+
+        // A binding without a modifier has nothing to release. On a quick tap
+        // the modifier may also be up before the grab took hold, and then no
+        // KeyRelease is coming to end the cycle.
+        let mask = self.cycle.as_ref().map_or( 0, |cycle| cycle.mask );
+        let (_, _, state) = self.query_pointer();
+        if self.clean_mask( state ) & mask == 0 {
+            self.end_cycle( time );
+        }
+    }
+
+    fn end_cycle( &mut self, time: c_ulong ) {
+        if self.cycle.take().is_none() {
+            return;
+        }
+
+        if self.keymaps.is_empty() {
+            unsafe {
+                xlib::XUngrabKeyboard( self.display, time );
+            }
+        }
+
+        let window = self.focuswin;
+        if self.get_client( window ).is_some() {
+            self.focus_history.retain( |&w| w != window );
+            self.focus_history.insert( 0, window );
+        }
+    }
+
+    // Keyboard layout or modifier mapping changed, so our keycodes and lock
+    // masks may be stale. Refresh Xlib's cache and grab everything again.
+    fn on_mapping_notify( &mut self, event: &mut xlib::XMappingEvent ) {
+        unsafe {
+            xlib::XRefreshKeyboardMapping( event );
+        }
+
+        if event.request == xlib::MappingKeyboard || event.request == xlib::MappingModifier {
+            self.grab_keys();
+            self.grab_buttons();
+        }
+    }
+
+    fn on_resize_move( &mut self, window: xlib::Window, event: &xlib::XButtonEvent ) {
+        let (gx, gy, gw, gh) = match self.get_geometry( window ) {
+            Some(geom) => geom,
+            None => return,
+        };
+        self.drag_geom = (gx, gy, gw, gh);
+
+        let mut shape = XC_FLEUR;
+        self.x = event.x_root;
+        self.y = event.y_root;
+
+        if event.button == 3 {
+            // Resize from whichever corner or edge is nearest the pointer and
+            // put the pointer right on it
+            let edge = nearest_edge( gx, gy, gw, gh, event.x_root, event.y_root );
+            let wx = match edge.0 { -1 => 0, 1 => gw - 1, _ => gw / 2 };
+            let wy = match edge.1 { -1 => 0, 1 => gh - 1, _ => gh / 2 };
+
+            self.drag_edge = edge;
+            self.x = gx + wx;
+            self.y = gy + wy;
+            shape = resize_cursor( edge );
+
+            unsafe {
+                xlib::XWarpPointer( self.display, 0, self.root, 0, 0, 0, 0, self.x, self.y );
+            }
+        }
+
+        self.button_id = event.button;
+        self.drag_window = window;
+        unsafe {
+            self.drag_cursor = xlib::XCreateFontCursor( self.display, shape );
+            xlib::XGrabPointer( self.display, window, 1,
+                (xlib::PointerMotionMask|xlib::ButtonReleaseMask) as u32,
+                xlib::GrabModeAsync, xlib::GrabModeAsync,
+                0, self.drag_cursor, event.time);
+        }
+
+        if config::WIREFRAME {
+            self.start_wireframe();
+        }
+    }
+
+    fn on_button_press( &mut self, event: &xlib::XButtonEvent ) {
+        self.last_input = event.time;
+        let button_info = config::MouseCmd::new( event.button, self.clean_mask( event.state ) );
+
+        // Plain clicks on a title bar are reported on the frame itself
+        if let Some(window) = self.frame_owner( event.window ) {
+            if button_info.modifier == 0 && event.y < config::TITLE_HEIGHT {
+                self.on_title_press( window, event );
+                return;
+            }
+        }
+
+        let target = if event.window == self.root { event.subwindow } else { event.window };
+        let target = self.client_of( target ).unwrap_or( target );
+
+        match button_info {
+            config::MOUSE_RESIZE => {
+                if target != 0 {
+                    self.focus( target, event.time );
+                    self.on_resize_move( target, &event );
                 }
             },
 
             config::MOUSE_MOVE => {
-                if event.subwindow != 0 {
-                    self.focus( event.subwindow, event.time );
-                    self.on_resize_move( &event );
+                if target != 0 {
+                    self.focus( target, event.time );
+                    self.on_resize_move( target, &event );
                 }
             },
 
             config::MOUSE_RAISE => {
-                if event.subwindow != 0 {
-                    self.focus( event.subwindow, event.time );
+                if target != 0 {
+                    self.focus( target, event.time );
+                    self.raise( target );
                 }
             },
 
@@ -492,9 +2473,46 @@ impl WindowSystem {
         }
     }
 
+    fn on_title_press( &mut self, window: xlib::Window, event: &xlib::XButtonEvent ) {
+        let button = match self.get_client( window ) {
+            Some(c) => c.frame.as_ref().and_then( |f| f.button_at( event.x, event.y, c.w ) ),
+            None => return,
+        };
+
+        match button {
+            Some(FrameButton::Close) => self.close_window( window, event.time ),
+            Some(FrameButton::Maximize) => self.toggle_maximize( window ),
+            Some(FrameButton::Minimize) => self.iconify( window, event.time ),
+            None => {
+                self.focus( window, event.time );
+                if event.button == 1 {
+                    self.on_resize_move( window, event );
+                }
+            },
+        }
+    }
+
     fn on_button_release( &mut self, event: &xlib::XButtonEvent ) {
+        let wireframe = self.end_wireframe();
+        self.hide_outline();
+
+        // A drop on a monitor edge wins over wherever the outline was
+        let target = match self.button_id {
+            1 => self.snap_target.take().or( wireframe ),
+            _ => wireframe,
+        };
+        if let Some((x, y, w, h)) = target {
+            let window = self.drag_window;
+            self.move_resize( window, x, y, w, h );
+        }
+        self.snap_target = None;
+
         unsafe {
             xlib::XUngrabPointer( self.display, event.time );
+            if self.drag_cursor != 0 {
+                xlib::XFreeCursor( self.display, self.drag_cursor );
+                self.drag_cursor = 0;
+            }
         }
     }
 
@@ -509,46 +2527,418 @@ impl WindowSystem {
         self.flush();
     }
 
+    // Resize relative to the geometry at button press. The edges opposite
+    // the one being dragged stay where they are.
     fn on_resize( &mut self, event: &xlib::XMotionEvent ) {
+        let (gx, gy, gw, gh) = self.drag_geom;
+        let (ex, ey) = self.drag_edge;
+
+        let dx = event.x_root - self.x;
+        let dy = event.y_root - self.y;
+
+        let (w, h) = self.hinted_size( event.window, gw + ex * dx, gh + ey * dy );
+        let x = if ex < 0 { gx + gw - w } else { gx };
+        let y = if ey < 0 { gy + gh - h } else { gy };
+
+        let (x, y, w, h) = self.clamp_geometry( event.window, x, y, w, h );
+        if config::WIREFRAME {
+            self.draw_wireframe( x, y, w, h );
+        } else {
+            self.configure_client( event.window, x, y, w, h );
+        }
+    }
+
+    // Move relative to the geometry at button press
+    fn on_move( &mut self, event: &xlib::XMotionEvent ) {
+        let (gx, gy, gw, gh) = self.drag_geom;
+
+        let (x, y) = self.snap_position( event.window,
+            gx + event.x_root - self.x, gy + event.y_root - self.y, gw, gh );
+        let (x, y, _, _) = self.clamp_geometry( event.window, x, y, gw, gh );
+
+        self.snap_target = if config::AERO_SNAP {
+            self.aero_target( event.x_root, event.y_root )
+        } else {
+            None
+        };
+
+        if config::WIREFRAME {
+            let (x, y, w, h) = self.snap_target.unwrap_or( (x, y, gw, gh) );
+            self.draw_wireframe( x, y, w, h );
+            return;
+        }
+
+        self.configure_client( event.window, x, y, gw, gh );
+        match self.snap_target {
+            Some((x, y, w, h)) => self.show_outline( x, y, w, h ),
+            None => self.hide_outline(),
+        }
+    }
+
+    // Outline mode draws the drag as an inverted rectangle on the root and
+    // leaves the client alone until the button is released. The server is
+    // grabbed meanwhile so nothing draws over the rectangle.
+    fn start_wireframe( &mut self ) {
+        unsafe {
+            if self.xor_gc.is_null() {
+                let screen = xlib::XDefaultScreen( self.display );
+                let mut gcv: xlib::XGCValues = ::std::mem::zeroed();
+                gcv.function = xlib::GXxor;
+                gcv.foreground = xlib::XWhitePixel( self.display, screen ) ^ xlib::XBlackPixel( self.display, screen );
+                gcv.line_width = config::WIREFRAME_WIDTH;
+                gcv.subwindow_mode = xlib::IncludeInferiors;
+                self.xor_gc = xlib::XCreateGC( self.display, self.root,
+                    ( xlib::GCFunction|xlib::GCForeground|xlib::GCLineWidth|xlib::GCSubwindowMode ) as c_ulong,
+                    &mut gcv );
+            }
+            xlib::XGrabServer( self.display );
+        }
+
+        let (x, y, w, h) = self.drag_geom;
+        self.draw_wireframe( x, y, w, h );
+    }
+
+    fn draw_wireframe( &mut self, x: i32, y: i32, w: i32, h: i32 ) {
+        if self.wireframe == Some( (x, y, w, h) ) {
+            return;
+        }
+
+        unsafe {
+            // Drawing the same rectangle again with XOR erases it
+            if let Some((ox, oy, ow, oh)) = self.wireframe {
+                xlib::XDrawRectangle( self.display, self.root, self.xor_gc, ox, oy, ow as u32, oh as u32 );
+            }
+            xlib::XDrawRectangle( self.display, self.root, self.xor_gc, x, y, w as u32, h as u32 );
+        }
+        self.wireframe = Some( (x, y, w, h) );
+        self.flush();
+    }
+
+    // Erase the rectangle and hand back where it was
+    fn end_wireframe( &mut self ) -> Option<(i32, i32, i32, i32)> {
+        let rect = self.wireframe.take()?;
+        unsafe {
+            xlib::XDrawRectangle( self.display, self.root, self.xor_gc,
+                rect.0, rect.1, rect.2 as u32, rect.3 as u32 );
+            xlib::XUngrabServer( self.display );
+        }
+        Some( rect )
+    }
+
+    // Clients answer from our own state, anything else asks the server
+    fn get_geometry( &self, window: xlib::Window ) -> Option<(i32, i32, i32, i32)> {
+        if let Some(c) = self.get_client( window ) {
+            return Some( (c.x, c.y, c.w, c.h) );
+        }
+
         unsafe {
             let mut wa = self.get_empty_wa();
-            if xlib::XGetWindowAttributes( self.display, event.window, &mut wa ) == 0 {
-                return;
+            if xlib::XGetWindowAttributes( self.display, window, &mut wa ) == 0 {
+                return None;
+            }
+            Some( (wa.x, wa.y, wa.width, wa.height) )
+        }
+    }
+
+    // Geometry of the focused client, if any
+    fn focused_geometry( &self ) -> Option<(i32, i32, i32, i32)> {
+        self.get_client( self.focuswin )?;
+        self.get_geometry( self.focuswin )
+    }
+
+    fn extents( &self, window: xlib::Window ) -> (i32, i32, i32, i32) {
+        self.get_client( window ).map_or( (0, 0, 0, 0), |c| c.extents() )
+    }
+
+    fn hinted_size( &self, window: xlib::Window, w: i32, h: i32 ) -> (i32, i32) {
+        match self.get_client( window ) {
+            Some(c) => c.apply_size_hints( w, h ),
+            None => ( max( 1, w ), max( 1, h ) ),
+        }
+    }
+
+    // Apply the size hints of a window and keep part of it on screen. Both
+    // the mouse and keyboard paths go through here.
+    fn clamp_geometry( &self, window: xlib::Window, x: i32, y: i32, w: i32, h: i32 ) -> (i32, i32, i32, i32) {
+        let (w, h) = self.hinted_size( window, w, h );
+
+        let (sw, sh) = unsafe {
+            let screen = xlib::XDefaultScreen( self.display );
+            ( xlib::XDisplayWidth( self.display, screen ), xlib::XDisplayHeight( self.display, screen ) )
+        };
+
+        let keep = config::KEEP_VISIBLE;
+        let x = x.max( keep.min( w ) - w ).min( sw - keep.min( w ) );
+        let y = y.max( 0 ).min( sh - keep.min( h ) );
+        (x, y, w, h)
+    }
+
+    // Read the monitor layout from Xinerama, or use the whole screen
+    fn update_monitors( &mut self ) {
+        self.monitors.clear();
+
+        unsafe {
+            if xinerama::XineramaIsActive( self.display ) != 0 {
+                let mut count = 0;
+                let info = xinerama::XineramaQueryScreens( self.display, &mut count );
+                if !info.is_null() {
+                    let screens = ::std::slice::from_raw_parts( info, count as usize );
+                    for s in screens.iter() {
+                        self.monitors.push( ( s.x_org as i32, s.y_org as i32, s.width as i32, s.height as i32 ) );
+                    }
+                    xlib::XFree( info as *mut _ );
+                }
             }
 
-            let xdiff = Wrapping::<u32>( event.x_root as u32 ) - Wrapping::<u32>( self.w );
-            let ydiff = Wrapping::<u32>( event.y_root as u32 ) - Wrapping::<u32>( self.h );
+            if self.monitors.is_empty() {
+                let screen = xlib::XDefaultScreen( self.display );
+                self.monitors.push( ( 0, 0, xlib::XDisplayWidth( self.display, screen ),
+                    xlib::XDisplayHeight( self.display, screen ) ) );
+            }
+        }
+    }
+
+    // The monitor containing a point, or the first one
+    fn monitor_at( &self, x: i32, y: i32 ) -> (i32, i32, i32, i32) {
+        self.monitors.iter()
+            .find( |&&(mx, my, mw, mh)| x >= mx && x < mx + mw && y >= my && y < my + mh )
+            .or( self.monitors.first() )
+            .cloned()
+            .unwrap_or( (0, 0, 1, 1) )
+    }
+
+    // Pull a window being moved onto nearby monitor edges and the edges of
+    // other clients
+    fn snap_position( &self, window: xlib::Window, x: i32, y: i32, w: i32, h: i32 ) -> (i32, i32) {
+        let dist = config::SNAP_DISTANCE;
+        if dist <= 0 {
+            return (x, y);
+        }
+
+        // Work with the outer size so borders meet instead of overlapping
+        let (l, r, t, b) = self.extents( window );
+        let w = w + l + r;
+        let h = h + t + b;
+
+        let mut xs = Vec::new();
+        let mut ys = Vec::new();
+
+        // Both the monitor edges and the edges of its work area, so windows
+        // can sit against a dock or slide under it
+        let monitor = self.monitor_at( x + w / 2, y + h / 2 );
+        for &(ax, ay, aw, ah) in [ monitor, self.work_area( monitor ) ].iter() {
+            xs.push( ax );
+            xs.push( ax + aw - w );
+            ys.push( ay );
+            ys.push( ay + ah - h );
+        }
+
+        for c in self.clients.iter().filter( |c| c.window != window && !c.hidden ) {
+            let (l, r, t, b) = c.extents();
+            let (cw, ch) = ( c.w + l + r, c.h + t + b );
+            // Only edges that actually face each other
+            if y < c.y + ch && y + h > c.y {
+                xs.push( c.x + cw );
+                xs.push( c.x - w );
+                xs.push( c.x );
+                xs.push( c.x + cw - w );
+            }
+            if x < c.x + cw && x + w > c.x {
+                ys.push( c.y + ch );
+                ys.push( c.y - h );
+                ys.push( c.y );
+                ys.push( c.y + ch - h );
+            }
+        }
 
-            self.w = event.x_root as u32;
-            self.h = event.y_root as u32;
+        let nearest = |pos: i32, targets: &Vec<i32>| {
+            targets.iter()
+                .filter( |&&t| ( t - pos ).abs() <= dist )
+                .min_by_key( |&&t| ( t - pos ).abs() )
+                .cloned()
+                .unwrap_or( pos )
+        };
+        ( nearest( x, &xs ), nearest( y, &ys ) )
+    }
 
-            let new_w = Wrapping::<u32>(wa.width as u32) + xdiff;
-            let new_h = Wrapping::<u32>(wa.height as u32) + ydiff;
+    // The geometry a window would take when dropped with the pointer here, if
+    // the pointer is on a monitor edge. Windows tile inside the work area.
+    fn aero_target( &self, px: i32, py: i32 ) -> Option<(i32, i32, i32, i32)> {
+        let monitor = self.monitor_at( px, py );
+        let (mx, my, mw, mh) = monitor;
+        let (wx, wy, ww, wh) = self.work_area( monitor );
+        let corner = config::AERO_CORNER;
+
+        let left = px <= mx;
+        let right = px >= mx + mw - 1;
+        let top = py <= my;
+        let near_top = py < my + corner;
+        let near_bottom = py >= my + mh - corner;
+
+        // Targets are client geometry, so the border fits inside the area
+        let (l, r, t, b) = self.extents( self.drag_window );
+        let fit = |(x, y, w, h): (i32, i32, i32, i32)| ( x, y, w - l - r, h - t - b );
+
+        let (hw, hh) = ( ww / 2, wh / 2 );
+        let target = if left || right {
+            let x = if left { wx } else { wx + ww - hw };
+            if near_top {
+                Some( (x, wy, hw, hh) )
+            } else if near_bottom {
+                Some( (x, wy + wh - hh, hw, hh) )
+            } else {
+                Some( (x, wy, hw, wh) )
+            }
+        } else if top {
+            Some( (wx, wy, ww, wh) )
+        } else {
+            None
+        };
+        target.map( fit )
+    }
 
-            let new_w = max(1, new_w.0);
-            let new_h = max(1, new_h.0);
+    fn show_outline( &mut self, x: i32, y: i32, w: i32, h: i32 ) {
+        if self.outline.is_none() {
+            self.outline = Some( unsafe {
+                Outline::new( self.display, self.root, self.palette.outline, config::SNAP_OUTLINE.size )
+            } );
+        }
+        if let Some(ref mut outline) = self.outline {
+            outline.show( x, y, w, h );
+        }
+    }
 
-            xlib::XResizeWindow( self.display, event.window, new_w, new_h );
+    fn hide_outline( &mut self ) {
+        if let Some(ref mut outline) = self.outline {
+            outline.hide();
         }
     }
 
-    fn on_move( &mut self, event: &xlib::XMotionEvent ) {
+    fn move_resize( &mut self, window: xlib::Window, x: i32, y: i32, w: i32, h: i32 ) {
+        let (x, y, w, h) = self.clamp_geometry( window, x, y, w, h );
+        self.configure_client( window, x, y, w, h );
+        self.flush();
+    }
+
+    // Ask for new geometry and record it straight away, so following events
+    // work from it without waiting for the ConfigureNotify to come back
+    fn configure_client( &mut self, window: xlib::Window, x: i32, y: i32, w: i32, h: i32 ) {
+        if let Some(c) = self.get_client_mut( window ) {
+            c.x = x;
+            c.y = y;
+            c.w = w;
+            c.h = h;
+        }
+
+        let outer = self.outer_window( window );
         unsafe {
-            let mut wa = self.get_empty_wa();
-            if xlib::XGetWindowAttributes( self.display, event.window, &mut wa ) == 0 {
+            if outer == window {
+                xlib::XMoveResizeWindow( self.display, window, x, y, w as u32, h as u32 );
                 return;
             }
 
-            let xdiff = event.x_root - self.x;
-            let ydiff = event.y_root - self.y;
+            xlib::XMoveResizeWindow( self.display, outer, x, y, w as u32, ( h + config::TITLE_HEIGHT ) as u32 );
+            xlib::XResizeWindow( self.display, window, w as u32, h as u32 );
+        }
+        // A reparented client does not see its frame move, so tell it where
+        // it is on the root
+        self.send_configure( window );
+    }
+
+    fn send_configure( &self, window: xlib::Window ) {
+        let c = match self.get_client( window ) {
+            Some(c) => c,
+            None => return,
+        };
+        let (l, _, t, _) = c.extents();
+
+        let configure = xlib::XConfigureEvent {
+            type_: xlib::ConfigureNotify,
+            serial: 0,
+            send_event: 1,
+            display: self.display,
+            event: window,
+            window,
+            x: c.x + l,
+            y: c.y + t,
+            width: c.w,
+            height: c.h,
+            border_width: 0,
+            above: 0,
+            override_redirect: 0,
+        };
+
+        unsafe {
+            let mut event = xlib::XEvent::from( configure );
+            xlib::XSendEvent( self.display, window, 0, xlib::StructureNotifyMask, &mut event );
+        }
+    }
+
+    fn move_focused( &mut self, dir: config::Direction ) {
+        use config::Direction;
+
+        if let Some((x, y, w, h)) = self.focused_geometry() {
+            let step = config::MOVE_STEP;
+            let (dx, dy) = match dir {
+                Direction::Left => ( -step, 0 ),
+                Direction::Right => ( step, 0 ),
+                Direction::Up => ( 0, -step ),
+                Direction::Down => ( 0, step ),
+            };
+            let window = self.focuswin;
+            self.move_resize( window, x + dx, y + dy, w, h );
+        }
+    }
 
-            self.x = event.x_root;
-            self.y = event.y_root;
+    // Right and Down grow the window, Left and Up shrink it
+    fn resize_focused( &mut self, dir: config::Direction ) {
+        use config::Direction;
+
+        let (x, y, w, h) = match self.focused_geometry() {
+            Some(geom) => geom,
+            None => return,
+        };
+        let (inc_w, inc_h) = match self.get_client( self.focuswin ) {
+            Some(c) => ( c.inc_w, c.inc_h ),
+            None => return,
+        };
+
+        let step_w = resize_step( inc_w );
+        let step_h = resize_step( inc_h );
+        let (dw, dh) = match dir {
+            Direction::Left => ( -step_w, 0 ),
+            Direction::Right => ( step_w, 0 ),
+            Direction::Up => ( 0, -step_h ),
+            Direction::Down => ( 0, step_h ),
+        };
+        let window = self.focuswin;
+        self.move_resize( window, x, y, w + dw, h + dh );
+    }
 
-            let new_x = wa.x + xdiff;
-            let new_y = wa.y + ydiff;
+    // Push the focused window against an edge of its monitor
+    fn snap_focused( &mut self, dir: config::Direction ) {
+        use config::Direction;
+
+        if let Some((x, y, w, h)) = self.focused_geometry() {
+            let (l, r, t, b) = self.extents( self.focuswin );
+            let (mx, my, mw, mh) = self.monitor_at( x + w / 2, y + h / 2 );
+            let (x, y) = match dir {
+                Direction::Left => ( mx, y ),
+                Direction::Right => ( mx + mw - w - l - r, y ),
+                Direction::Up => ( x, my ),
+                Direction::Down => ( x, my + mh - h - t - b ),
+            };
+            let window = self.focuswin;
+            self.move_resize( window, x, y, w, h );
+        }
+    }
 
-            xlib::XMoveWindow( self.display, event.window, new_x, new_y );
+    fn center_focused( &mut self ) {
+        if let Some((x, y, w, h)) = self.focused_geometry() {
+            let (l, r, t, b) = self.extents( self.focuswin );
+            let (mx, my, mw, mh) = self.monitor_at( x + w / 2, y + h / 2 );
+            let window = self.focuswin;
+            self.move_resize( window, mx + ( mw - w - l - r ) / 2, my + ( mh - h - t - b ) / 2, w, h );
         }
     }
 
@@ -582,3 +2972,126 @@ impl WindowSystem {
         }
     }
 }
+
+impl Drop for WindowSystem {
+    // Put every client back on the root, visible, so nothing is lost when we
+    // exit
+    fn drop( &mut self ) {
+        let windows: Vec<xlib::Window> = self.clients.iter().map( |c| c.window ).collect();
+        for window in windows {
+            self.deiconify( window );
+            self.unframe( window );
+        }
+        unsafe {
+            xlib::XSync( self.display, 0 );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{free_area, tile_rects};
+
+    // Paint the rings the way draw_borders does into a grid standing in for
+    // the pixmap, then read every border pixel back through X's tiling
+    fn check_rings( rings: &[i32], w: i32, h: i32 ) {
+        let bw: i32 = rings.iter().sum();
+        let (ow, oh) = ( w + 2 * bw, h + 2 * bw );
+        let mut pixmap = vec![ None; ( ow * oh ) as usize ];
+
+        let mut inset = 0;
+        for (ring, &size) in rings.iter().enumerate() {
+            for (x, y, rw, rh) in tile_rects( bw, ( inset, inset, ow - 2 * inset, oh - 2 * inset ), ( ow, oh ) ) {
+                assert!( x >= 0 && y >= 0 && x + rw <= ow && y + rh <= oh, "piece outside the pixmap" );
+                for py in y .. y + rh {
+                    for px in x .. x + rw {
+                        pixmap[( py * ow + px ) as usize] = Some( ring );
+                    }
+                }
+            }
+            inset += size;
+        }
+
+        for oy in 0 .. oh {
+            for ox in 0 .. ow {
+                let depth = ox.min( oy ).min( ow - 1 - ox ).min( oh - 1 - oy );
+                if depth >= bw {
+                    continue;
+                }
+                let expected = rings.iter()
+                    .scan( 0, |end, &size| { *end += size; Some( *end ) } )
+                    .position( |end| depth < end );
+
+                // The border pixel at the outer corner is pixmap pixel (-bw, -bw)
+                let px = ( ox - bw ).rem_euclid( ow );
+                let py = ( oy - bw ).rem_euclid( oh );
+                assert_eq!( pixmap[( py * ow + px ) as usize], expected,
+                            "rings {:?} around {}x{}: wrong ring at {},{}", rings, w, h, ox, oy );
+            }
+        }
+    }
+
+    #[test]
+    fn equal_rings() {
+        check_rings( &[ 2, 2, 2 ], 40, 30 );
+    }
+
+    #[test]
+    fn uneven_rings() {
+        check_rings( &[ 1, 3, 2 ], 17, 5 );
+    }
+
+    #[test]
+    fn single_ring() {
+        check_rings( &[ 4 ], 10, 10 );
+    }
+
+    #[test]
+    fn window_smaller_than_the_border() {
+        check_rings( &[ 3, 3 ], 1, 1 );
+    }
+
+    #[test]
+    fn empty_rectangles_are_skipped() {
+        assert!( tile_rects( 2, ( 0, 0, 0, 5 ), ( 10, 10 ) ).is_empty() );
+    }
+
+    // Two 1920x1080 monitors side by side
+    const LEFT: (i32, i32, i32, i32) = ( 0, 0, 1920, 1080 );
+    const RIGHT: (i32, i32, i32, i32) = ( 1920, 0, 1920, 1080 );
+    const SCREEN: (i32, i32) = ( 3840, 1080 );
+
+    #[test]
+    fn no_struts_leave_the_monitor() {
+        assert_eq!( free_area( LEFT, SCREEN, &[] ), LEFT );
+    }
+
+    #[test]
+    fn partial_strut_only_covers_its_monitor() {
+        // A 30 pixel bar along the top of the left monitor
+        let bar = [ 0, 0, 30, 0, 0, 0, 0, 0, 0, 1919, 0, 0 ];
+        assert_eq!( free_area( LEFT, SCREEN, &[ bar ] ), ( 0, 30, 1920, 1050 ) );
+        assert_eq!( free_area( RIGHT, SCREEN, &[ bar ] ), RIGHT );
+    }
+
+    #[test]
+    fn side_struts_are_measured_from_the_screen_edge() {
+        // A 64 pixel dock on the right edge of the right monitor
+        let dock = [ 0, 64, 0, 0, 0, 0, 0, 1079, 0, 0, 0, 0 ];
+        assert_eq!( free_area( RIGHT, SCREEN, &[ dock ] ), ( 1920, 0, 1856, 1080 ) );
+        assert_eq!( free_area( LEFT, SCREEN, &[ dock ] ), LEFT );
+    }
+
+    #[test]
+    fn struts_add_up() {
+        let top = [ 0, 0, 24, 0, 0, 0, 0, 0, 0, i32::MAX, 0, 0 ];
+        let bottom = [ 0, 0, 0, 40, 0, 0, 0, 0, 0, 0, 0, i32::MAX ];
+        assert_eq!( free_area( LEFT, SCREEN, &[ top, bottom ] ), ( 0, 24, 1920, 1016 ) );
+    }
+
+    #[test]
+    fn struts_covering_the_monitor_are_ignored() {
+        let huge = [ 0, 0, 2000, 0, 0, 0, 0, 0, 0, i32::MAX, 0, 0 ];
+        assert_eq!( free_area( LEFT, SCREEN, &[ huge ] ), LEFT );
+    }
+}
@@ -0,0 +1,138 @@
+// Maps a window under alwm running on Xvfb and reads the border back with
+// XGetImage to check every ring lands where it should.
+//
+//     cargo test --features xvfb --test borders
+//
+// Opt in through the xvfb feature. Needs Xvfb on the PATH and fails if it
+// cannot run. ALWM_TEST_DISPLAY picks the display (default :98).
+
+extern crate alwm;
+extern crate libc;
+extern crate x11;
+
+use std::env;
+use std::ffi::CString;
+use std::process::{Child, Command};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use libc::c_ulong;
+use x11::xlib;
+
+use alwm::color::Color;
+use alwm::config;
+
+struct Reaper( Vec<Child> );
+
+impl Drop for Reaper {
+    fn drop( &mut self ) {
+        for child in self.0.iter_mut().rev() {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+    }
+}
+
+fn open_display( name: &str ) -> Option<*mut xlib::Display> {
+    let name = CString::new( name ).unwrap();
+    for _ in 0 .. 50 {
+        let display = unsafe { xlib::XOpenDisplay( name.as_ptr() ) };
+        if !display.is_null() {
+            return Some( display );
+        }
+        sleep( Duration::from_millis( 100 ) );
+    }
+    None
+}
+
+// Wait until alwm has given the window a border, or give up after 10s
+unsafe fn wait_for_border( display: *mut xlib::Display, window: xlib::Window ) -> Option<xlib::XWindowAttributes> {
+    let deadline = Instant::now() + Duration::from_secs( 10 );
+    let mut wa: xlib::XWindowAttributes = ::std::mem::zeroed();
+
+    while Instant::now() < deadline {
+        xlib::XSync( display, 0 );
+        if xlib::XGetWindowAttributes( display, window, &mut wa ) != 0 &&
+           wa.border_width > 0 && wa.map_state == xlib::IsViewable {
+            // Give the border pixmap a moment to follow the width
+            sleep( Duration::from_millis( 200 ) );
+            xlib::XGetWindowAttributes( display, window, &mut wa );
+            return Some( wa );
+        }
+        sleep( Duration::from_millis( 20 ) );
+    }
+    None
+}
+
+#[test]
+fn rings_are_drawn_around_the_window() {
+    let display_name = env::var( "ALWM_TEST_DISPLAY" ).unwrap_or( ":98".to_string() );
+    let mut children = Reaper( Vec::new() );
+
+    children.0.push( Command::new( "Xvfb" ).args( [ &display_name[..], "-screen", "0", "1280x1024x24" ] )
+        .spawn()
+        .unwrap_or_else( |e| panic!( "border test needs Xvfb on the PATH: {}", e ) ) );
+
+    let display = open_display( &display_name )
+        .unwrap_or_else( || panic!( "Xvfb did not come up on {}", display_name ) );
+
+    children.0.push( Command::new( env!( "CARGO_BIN_EXE_alwm" ) )
+        .env( "DISPLAY", &display_name )
+        .spawn()
+        .expect( "cannot start alwm" ) );
+    sleep( Duration::from_millis( 500 ) );
+
+    unsafe {
+        let root = xlib::XDefaultRootWindow( display );
+        let window = xlib::XCreateSimpleWindow( display, root, 200, 150, 400, 300, 0, 0, 0 );
+        xlib::XMapWindow( display, window );
+
+        let wa = wait_for_border( display, window ).expect( "alwm never gave the window a border" );
+
+        let mut focused = 0;
+        let mut revert = 0;
+        xlib::XGetInputFocus( display, &mut focused, &mut revert );
+        let borders = if focused == window { config::FOCUS_BORDERS } else { config::UNFOCUSED_BORDERS };
+
+        let bw: i32 = borders.iter().map( |b| b.size ).sum();
+        assert_eq!( wa.border_width, bw, "border width is not the sum of the rings" );
+
+        // x and y are the outer corner of the border
+        let outer_w = wa.width + 2 * bw;
+        let outer_h = wa.height + 2 * bw;
+        let image = xlib::XGetImage( display, root, wa.x, wa.y, outer_w as u32, outer_h as u32,
+                                     xlib::XAllPlanes(), xlib::ZPixmap );
+        assert!( !image.is_null(), "XGetImage failed" );
+
+        let mut inset = 0;
+        for (i, border) in borders.iter().enumerate() {
+            if border.size <= 0 {
+                continue;
+            }
+            let expected: c_ulong = Color::parse( border.color )
+                .and_then( |c| c.alloc( display ) )
+                .expect( "border colour does not allocate" );
+
+            // Both edges of the ring on every side, plus its corners
+            let (near, far) = ( inset, inset + border.size - 1 );
+            let samples = [
+                ( near, outer_h / 2 ), ( far, outer_h / 2 ),
+                ( outer_w - 1 - near, outer_h / 2 ), ( outer_w - 1 - far, outer_h / 2 ),
+                ( outer_w / 2, near ), ( outer_w / 2, far ),
+                ( outer_w / 2, outer_h - 1 - near ), ( outer_w / 2, outer_h - 1 - far ),
+                ( near, near ), ( outer_w - 1 - near, near ),
+                ( near, outer_h - 1 - near ), ( outer_w - 1 - near, outer_h - 1 - near ),
+            ];
+            for &(x, y) in samples.iter() {
+                let pixel = xlib::XGetPixel( image, x, y );
+                assert_eq!( pixel, expected, "ring {} ({}) has the wrong colour at {},{}",
+                            i, border.color, x, y );
+            }
+            inset += border.size;
+        }
+
+        xlib::XDestroyImage( image );
+        xlib::XDestroyWindow( display, window );
+        xlib::XCloseDisplay( display );
+    }
+}